@@ -1,27 +1,85 @@
 
 
-use std::{any::Any, fs::File, io::Read, sync::Arc};
+use std::{collections::HashMap, error::Error, net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
 
-use axum::{body::{self, Bytes}, extract::{Path, State}, http::{Response, StatusCode}, response::IntoResponse, routing::get, Json, Router};
+use axum::{body::{self, Bytes}, extract::{multipart::MultipartRejection, rejection::{JsonRejection, QueryRejection}, DefaultBodyLimit, Multipart, Path, Query, State}, extract::{ConnectInfo, Request}, http::{header::{ACCEPT_RANGES, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE, RETRY_AFTER, WWW_AUTHENTICATE}, HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode}, middleware::{self, Next}, response::IntoResponse, routing::{delete, get, patch, post, put}, Extension, Json, Router};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ipnet::IpNet;
 use log::warn;
+use tokio_util::io::ReaderStream;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use serde::{Deserialize, Serialize};
-use tokio::io::AsyncReadExt;
-use crate::{config::Config, db::{DbError, ReleaseDatabase}, release::{Artifact, Channel, Release, Repository}};
+use sha2::{Digest, Sha256};
+use crate::{audit::{AuditEntry, AuditFilter}, auth::{self, Action, Token}, conditional, config::{Config, Cors}, jwt::{self, JwtVerifier}, range::{self, ByteRanges}, rate_limit::RateLimiter, db::{unix_time, DbError, ReleaseDatabase, StoredArtifact}, release::{Artifact, ArtifactRevision, ArtifactType, Channel, Hashes, Release, Repository, UploadSession}, storage};
+
+/// Set by reverse proxies to the addresses a request was forwarded for.
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+/// Checksums of a whole file, sent along with any part of it (RFC 9530).
+const REPR_DIGEST: &str = "repr-digest";
+/// Older form of `Repr-Digest` still expected by some clients (RFC 3230).
+const DIGEST: &str = "digest";
 
 pub struct Api {
-    config: Arc<Config>
+    config: Arc<Config>,
+    metadata_limiter: Option<RateLimiter>,
+    download_limiter: Option<RateLimiter>,
+    jwt_verifier: Option<JwtVerifier>
 }
 
-impl Api {
-    pub fn new(config: Config) -> Api {
+/// State of the middleware guarding routes that need a grant for `action`.
+#[derive(Clone)]
+struct Guard {
+    api: Arc<Api>,
+    action: Action
+}
+
+impl Guard {
+    fn new(api: &Arc<Api>, action: Action) -> Self {
         Self {
-            config: Arc::new(config)
+            api: api.clone(),
+            action
         }
     }
+}
+
+impl Api {
+    pub fn new(config: Config) -> Result<Api, Box<dyn Error>> {
+        Ok(Self {
+            metadata_limiter: config.metadata_rate_limit().map(RateLimiter::new),
+            download_limiter: config.download_rate_limit().map(RateLimiter::new),
+            jwt_verifier: config.jwt().cloned().map(JwtVerifier::new).transpose()?,
+            config: Arc::new(config)
+        })
+    }
 
     pub async fn run(self) {
+        if self.config.seed_db() {
+            if let Err(e) = ReleaseDatabase::new(self.config.db_path()).and_then(|db| db.seed_db()) {
+                warn!("Failed to seed database: {}", e.to_string());
+            }
+        }
+
         let shared_state = Arc::new(self);
-        let app = Router::new()
+
+        let cleanup_state = shared_state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                Self::remove_expired_uploads(&cleanup_state).await;
+                for limiter in [&cleanup_state.metadata_limiter, &cleanup_state.download_limiter].into_iter().flatten() {
+                    limiter.prune();
+                }
+                if let Some(verifier) = &cleanup_state.jwt_verifier {
+                    verifier.reload();
+                }
+            }
+        });
+
+        // Reads are public, but a valid token may be given to also see drafts and private channels.
+        // Clients may revalidate what they already have with `If-None-Match`, and downloads also
+        // with `If-Modified-Since`.
+        let reads = Router::new()
             .route("/:repository", get(Self::get_repository))
             .route("/:repository/", get(Self::get_repository))
             .route("/:repository/:channel", get(Self::get_repository_channel))
@@ -31,14 +89,320 @@ impl Api {
             .route("/:repository/:channel/:release/:artifact", get(Self::get_repository_channel_release_artifact))
             .route("/:repository/:channel/:release/:artifact/", get(Self::get_repository_channel_release_artifact))
             .route("/:repository/:channel/:release/:artifact/download", get(Self::get_repository_channel_release_artifact_download))
+            .route("/:repository/:channel/:release/:artifact/revisions", get(Self::get_repository_channel_release_artifact_revisions))
+            .route("/:repository/:channel/:release/:artifact/revisions/", get(Self::get_repository_channel_release_artifact_revisions))
+            .route("/:repository/:channel/:release/:artifact/revisions/:revision", get(Self::get_repository_channel_release_artifact_revision))
+            .route("/:repository/:channel/:release/:artifact/revisions/:revision/", get(Self::get_repository_channel_release_artifact_revision))
+            .route("/:repository/:channel/:release/:artifact/revisions/:revision/download", get(Self::get_repository_channel_release_artifact_revision_download))
+            .route_layer(middleware::from_fn_with_state(shared_state.clone(), Self::identify))
+            .route_layer(middleware::from_fn(Self::conditional));
+
+        // Writes and the audit log need a token with a grant for the action on the
+        // route's repository and channel. Routes without a repository need wildcard grants.
+        let admin_writes = Router::new()
+            .route("/", post(Self::post_root))
+            .route("/admin/audit", get(Self::get_admin_audit))
+            .route("/admin/audit/", get(Self::get_admin_audit))
+            .route("/:repository", post(Self::post_repository).patch(Self::patch_repository).delete(Self::delete_repository))
+            .route("/:repository/", post(Self::post_repository).patch(Self::patch_repository).delete(Self::delete_repository))
+            .route("/:repository/:channel", patch(Self::patch_repository_channel).delete(Self::delete_repository_channel))
+            .route("/:repository/:channel/", patch(Self::patch_repository_channel).delete(Self::delete_repository_channel))
+            .route_layer(middleware::from_fn_with_state(Guard::new(&shared_state, Action::Admin), Self::authorize))
+            .route_layer(middleware::from_fn_with_state(shared_state.clone(), Self::restrict_network));
+
+        let publish_writes = Router::new()
+            .route("/:repository/:channel", post(Self::post_repository_channel))
+            .route("/:repository/:channel/", post(Self::post_repository_channel))
+            .route("/:repository/:channel/:release", patch(Self::patch_repository_channel_release))
+            .route("/:repository/:channel/:release/", patch(Self::patch_repository_channel_release))
+            .route("/:repository/:channel/:release/bundle", post(Self::post_repository_channel_release_bundle))
+            .route("/:repository/:channel/:release/bundle/", post(Self::post_repository_channel_release_bundle))
+            .route("/:repository/:channel/:release/publish", post(Self::post_repository_channel_release_publish))
+            .route("/:repository/:channel/:release/publish/", post(Self::post_repository_channel_release_publish))
+            .route("/:repository/:channel/:release/uploads", post(Self::post_repository_channel_release_uploads))
+            .route("/:repository/:channel/:release/uploads/", post(Self::post_repository_channel_release_uploads))
+            .route("/:repository/:channel/:release/uploads/:session", get(Self::get_repository_channel_release_upload).delete(Self::delete_repository_channel_release_upload))
+            .route("/:repository/:channel/:release/uploads/:session/", get(Self::get_repository_channel_release_upload).delete(Self::delete_repository_channel_release_upload))
+            .route("/:repository/:channel/:release/uploads/:session/:chunk", put(Self::put_repository_channel_release_upload_chunk))
+            .route("/:repository/:channel/:release/uploads/:session/finalize", post(Self::post_repository_channel_release_upload_finalize))
+            .route("/:repository/:channel/:release/artifacts", post(Self::post_repository_channel_release_artifacts))
+            .route("/:repository/:channel/:release/artifacts/", post(Self::post_repository_channel_release_artifacts))
+            .route("/:repository/:channel/:release/:artifact", patch(Self::patch_repository_channel_release_artifact))
+            .route("/:repository/:channel/:release/:artifact/", patch(Self::patch_repository_channel_release_artifact))
+            .route("/:repository/:channel/:release/:artifact/file", put(Self::put_repository_channel_release_artifact_file))
+            .route_layer(middleware::from_fn_with_state(Guard::new(&shared_state, Action::Publish), Self::authorize))
+            .route_layer(middleware::from_fn_with_state(shared_state.clone(), Self::restrict_network));
+
+        // The target channel is only known to the handler, which checks the promote grant on it.
+        let promote_writes = Router::new()
+            .route("/:repository/:channel/:release/promote", post(Self::post_repository_channel_release_promote))
+            .route("/:repository/:channel/:release/promote/", post(Self::post_repository_channel_release_promote))
+            .route_layer(middleware::from_fn_with_state(Guard::new(&shared_state, Action::Read), Self::authorize))
+            .route_layer(middleware::from_fn_with_state(shared_state.clone(), Self::restrict_network));
+
+        // Anyone who may read a channel may share its artifacts through signed URLs, but only
+        // publishers may share those of drafts.
+        let signing = Router::new()
+            .route("/:repository/:channel/:release/:artifact/sign", post(Self::post_repository_channel_release_artifact_sign))
+            .route("/:repository/:channel/:release/:artifact/sign/", post(Self::post_repository_channel_release_artifact_sign))
+            .route_layer(middleware::from_fn_with_state(Guard::new(&shared_state, Action::Read), Self::authorize))
+            .route_layer(middleware::from_fn_with_state(shared_state.clone(), Self::restrict_network));
+
+        let delete_writes = Router::new()
+            .route("/:repository/:channel/:release", delete(Self::delete_repository_channel_release))
+            .route("/:repository/:channel/:release/", delete(Self::delete_repository_channel_release))
+            .route_layer(middleware::from_fn_with_state(Guard::new(&shared_state, Action::Delete), Self::authorize))
+            .route_layer(middleware::from_fn_with_state(shared_state.clone(), Self::restrict_network));
+
+        let mut app = Router::new()
+            .merge(reads)
+            .merge(admin_writes)
+            .merge(publish_writes)
+            .merge(promote_writes)
+            .merge(delete_writes)
+            .merge(signing)
+            .layer(DefaultBodyLimit::max(shared_state.config.max_upload_size()))
+            .layer(middleware::from_fn_with_state(shared_state.clone(), Self::rate_limit))
             .with_state(shared_state.clone());
+        // Outermost, so preflight requests are answered before rate limiting and authentication.
+        if let Some(cors) = shared_state.config.cors() {
+            app = app.layer(cors_layer(cors));
+        }
 
         let listener = tokio::net::TcpListener::bind(shared_state.config.bind_addr()).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+    }
+
+    /// Rejects requests from outside the networks allowed to use the admin and
+    /// mutating routes.
+    async fn restrict_network(
+        State(state): State<Arc<Api>>,
+        ConnectInfo(peer): ConnectInfo<SocketAddr>,
+        request: Request,
+        next: Next
+    ) -> Response<body::Body> {
+        if let Some(networks) = state.config.write_allowed_networks() {
+            let addr = client_addr(state.config.trusted_proxies(), peer, request.headers());
+            if !addr.is_some_and(|addr| networks.iter().any(|network| network.contains(&addr))) {
+                let addr = addr.map_or("an unknown address".to_string(), |addr| addr.to_string());
+                warn!("Rejected {} {} from {}: not in an allowed network", request.method(), request.uri().path(), addr);
+                return (StatusCode::FORBIDDEN, Json(ResponseJson { response_code: 11, data: ResponseData::Error("Not allowed from this network".to_string()) })).into_response()
+            }
+        }
+
+        next.run(request).await
+    }
+
+    /// Applies the per-client rate limit of the route. Clients with a valid
+    /// token are limited per token, others per address, so requests with made
+    /// up tokens count against their address. The verified token is passed on,
+    /// so it isn't looked up again.
+    async fn rate_limit(
+        State(state): State<Arc<Api>>,
+        ConnectInfo(peer): ConnectInfo<SocketAddr>,
+        mut request: Request,
+        next: Next
+    ) -> Response<body::Body> {
+        let limiter = if request.uri().path().trim_end_matches('/').ends_with("/download") {
+            &state.download_limiter
+        } else {
+            &state.metadata_limiter
+        };
+        let Some(limiter) = limiter else {
+            return next.run(request).await
+        };
+
+        let token = Self::bearer_token(&state, request.headers());
+        let key = match &token {
+            Ok(Some(token)) => match token.id() {
+                Some(id) => format!("token:{id}"),
+                None => format!("external:{}", token.name())
+            },
+            // Clients behind a proxy that didn't say who they are share a bucket.
+            _ => match client_addr(state.config.trusted_proxies(), peer, request.headers()) {
+                Some(addr) => format!("addr:{addr}"),
+                None => "addr:unknown".to_string()
+            }
+        };
+        if let Err(retry_after) = limiter.check(&key) {
+            let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, retry_after.to_string())], Json(ResponseJson { response_code: 12, data: ResponseData::Error("Too many requests".to_string()) })).into_response()
+        }
+
+        match token {
+            Ok(Some(token)) => {
+                request.extensions_mut().insert(token);
+                next.run(request).await
+            },
+            Ok(None) => next.run(request).await,
+            Err(response) => response
+        }
+    }
+
+    /// Requires a valid bearer token with a grant for the guarded action on the
+    /// repository and channel in the path, and makes it available to the handler.
+    async fn authorize(
+        State(guard): State<Guard>,
+        params: Option<Path<HashMap<String, String>>>,
+        mut request: Request,
+        next: Next
+    ) -> Response<body::Body> {
+        let token = match Self::request_token(&guard.api, &request) {
+            Ok(Some(token)) => token,
+            Ok(None) => return unauthorized_response("Missing bearer token"),
+            Err(response) => return response
+        };
+
+        let params = params.map(|Path(params)| params).unwrap_or_default();
+        let repository = params.get("repository").map(String::as_str);
+        let channel = params.get("channel").map(String::as_str);
+        if !token.allows(repository, channel, guard.action) {
+            return forbidden_response(guard.action).into_response()
+        }
+
+        request.extensions_mut().insert(token);
+        next.run(request).await
+    }
+
+    /// Makes a bearer token available to the handler if one was sent. An
+    /// invalid token is still rejected.
+    async fn identify(
+        State(state): State<Arc<Api>>,
+        mut request: Request,
+        next: Next
+    ) -> Response<body::Body> {
+        match Self::request_token(&state, &request) {
+            Ok(Some(token)) => {
+                request.extensions_mut().insert(token);
+                next.run(request).await
+            },
+            Ok(None) => next.run(request).await,
+            Err(response) => response
+        }
+    }
+
+    /// Answers `304 Not Modified` if the client's copy of the response is still
+    /// current. JSON responses get a weak ETag computed from their body, unless
+    /// the handler set an ETag itself or they are downloads of a JSON file.
+    async fn conditional(request: Request, next: Next) -> Response<body::Body> {
+        let request_headers = request.headers().clone();
+        let response = next.run(request).await;
+        if response.status() != StatusCode::OK && response.status() != StatusCode::PARTIAL_CONTENT {
+            return response
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let is_json = parts.headers.get(CONTENT_TYPE).is_some_and(|t| t.as_bytes().starts_with(b"application/json"));
+        let body = if is_json && !parts.headers.contains_key(ETAG) && !parts.headers.contains_key(CONTENT_DISPOSITION) {
+            let bytes = match body::to_bytes(body, usize::MAX).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    warn!("Failed to buffer response: {}", e.to_string());
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
+                }
+            };
+            if let Ok(etag) = HeaderValue::from_str(&conditional::weak_etag(&bytes)) {
+                parts.headers.insert(ETAG, etag);
+            }
+            body::Body::from(bytes)
+        } else {
+            body
+        };
+
+        if conditional::is_not_modified(&request_headers, parts.headers.get(ETAG), parts.headers.get(LAST_MODIFIED)) {
+            parts.status = StatusCode::NOT_MODIFIED;
+            for header in [CONTENT_LENGTH, CONTENT_TYPE, CONTENT_RANGE] {
+                parts.headers.remove(header);
+            }
+            return Response::from_parts(parts, body::Body::empty())
+        }
+        Response::from_parts(parts, body)
+    }
+
+    /// Token of a request, if the rate limiter already verified it, or else
+    /// its bearer token.
+    #[allow(clippy::result_large_err)]
+    fn request_token(state: &Api, request: &Request) -> Result<Option<Token>, Response<body::Body>> {
+        match request.extensions().get::<Token>() {
+            Some(token) => Ok(Some(token.clone())),
+            None => Self::bearer_token(state, request.headers())
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn bearer_token(state: &Api, headers: &HeaderMap) -> Result<Option<Token>, Response<body::Body>> {
+        let Some(header) = headers.get(AUTHORIZATION) else {
+            return Ok(None)
+        };
+        let Some(secret) = header.to_str().ok().and_then(|h| h.strip_prefix("Bearer ")) else {
+            return Err(unauthorized_response("Malformed authorization header"))
+        };
+        let secret = secret.trim();
+
+        if let Some(verifier) = state.jwt_verifier.as_ref().filter(|_| jwt::is_jwt(secret)) {
+            return match verifier.verify(secret) {
+                Ok(token) => Ok(Some(token)),
+                Err(e) => {
+                    warn!("Rejected JWT: {}", e.to_string());
+                    Err(unauthorized_response("Invalid bearer token"))
+                }
+            }
+        }
+
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response())
+            }
+        };
+
+        match db.authenticate_token(secret) {
+            Ok(token) => Ok(Some(token)),
+            Err(_) => Err(unauthorized_response("Invalid bearer token"))
+        }
+    }
+
+    async fn remove_expired_uploads(state: &Api) {
+        let session_ids = match ReleaseDatabase::new(state.config.db_path()).and_then(|db| db.delete_expired_upload_sessions()) {
+            Ok(session_ids) => session_ids,
+            Err(e) => {
+                warn!("Failed to remove expired upload sessions: {}", e.to_string());
+                return
+            }
+        };
+
+        for session_id in session_ids {
+            storage::remove_upload(state.config.data_dir(), &session_id).await;
+        }
+    }
+
+    async fn get_admin_audit(
+        State(state): State<Arc<Api>>,
+        query: Result<Query<AuditFilter>, QueryRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Query(query) = match query {
+            Ok(query) => query,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.read_audit_log(&query) {
+            Ok(entries) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::AuditLog(entries) })),
+            Err(e) => db_error_response(e)
+        }
     }
 
     async fn get_repository(
         State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
         Path(repository): Path<String>
     ) -> (StatusCode, Json<ResponseJson>) {
         let db = match ReleaseDatabase::new(state.config.db_path()) {
@@ -49,14 +413,108 @@ impl Api {
             }
         };
 
-        match db.read_repository(repository) {
+        match db.read_repository(&repository, |channel| include_private(&token, &repository, channel), |channel| include_drafts(&token, &repository, channel)) {
             Ok(repository) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Repository(repository) })),
             Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
         }
     }
 
+    async fn post_root(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        body: Result<Json<KeyJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.create_repository(body.id) {
+            Ok(result) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Repository(result) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn post_repository(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path(repository): Path<String>,
+        body: Result<Json<CreateChannelJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.create_channel(repository, body.id, body.private) {
+            Ok(result) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Channel(result) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn patch_repository(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path(repository): Path<String>,
+        body: Result<Json<KeyJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.rename_repository(repository, body.id) {
+            Ok(result) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Repository(result) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn delete_repository(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path(repository): Path<String>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.delete_repository(repository) {
+            Ok(()) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::None })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
     async fn get_repository_channel(
         State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
         Path((repository, channel)): Path<(String, String)>
     ) -> (StatusCode, Json<ResponseJson>) {
         let db = match ReleaseDatabase::new(state.config.db_path()) {
@@ -67,14 +525,86 @@ impl Api {
             }
         };
 
-        match db.read_channel(repository, channel) {
+        let include_private = include_private(&token, &repository, &channel);
+        let include_drafts = include_drafts(&token, &repository, &channel);
+        match db.read_channel(repository, channel, include_private, include_drafts) {
             Ok(channel) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Channel(channel) })),
             Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
         }
     }
 
+    async fn post_repository_channel(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel)): Path<(String, String)>,
+        body: Result<Json<CreateReleaseJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.create_release(repository, channel, body.id, body.name, body.created_at) {
+            Ok(release) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn patch_repository_channel(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel)): Path<(String, String)>,
+        body: Result<Json<UpdateChannelJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.update_channel(repository, channel, body.id, body.private) {
+            Ok(result) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Channel(result) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn delete_repository_channel(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel)): Path<(String, String)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.delete_channel(repository, channel) {
+            Ok(()) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::None })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
     async fn get_repository_channel_release(
         State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
         Path((repository, channel, release)): Path<(String, String, String)>
     ) -> (StatusCode, Json<ResponseJson>) {
         let db = match ReleaseDatabase::new(state.config.db_path()) {
@@ -85,17 +615,26 @@ impl Api {
             }
         };
 
-        match db.read_release(repository, channel, release) {
+        let include_private = include_private(&token, &repository, &channel);
+        let include_drafts = include_drafts(&token, &repository, &channel);
+        match db.read_release(repository, channel, release, include_private, include_drafts) {
             Ok(release) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
             Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
         }    
     }
 
-    async fn get_repository_channel_release_artifact(
+    async fn patch_repository_channel_release(
         State(state): State<Arc<Api>>,
-        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>,
+        body: Result<Json<UpdateReleaseJson>, JsonRejection>
     ) -> (StatusCode, Json<ResponseJson>) {
-        let db = match ReleaseDatabase::new(state.config.db_path()) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
             Ok(db) => db,
             Err(e) => {
                 warn!("Failed to open database: {}", e.to_string());
@@ -103,65 +642,1189 @@ impl Api {
             }
         };
 
-        match db.read_artifact(repository, channel, release, artifact) {
-            Ok(artifact) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Artifact(artifact) })),
-            Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+        match db.update_release(repository, channel, release, body.name, body.created_at) {
+            Ok(release) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
+            Err(e) => db_error_response(e)
         }
     }
 
-    async fn get_repository_channel_release_artifact_download(
+    async fn delete_repository_channel_release(
         State(state): State<Arc<Api>>,
-        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>
-    ) -> Response<body::Body> {
-        let db = match ReleaseDatabase::new(state.config.db_path()) {
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>,
+        query: Result<Query<DeleteReleaseQuery>, QueryRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Query(query) = match query {
+            Ok(query) => query,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
             Ok(db) => db,
             Err(e) => {
                 warn!("Failed to open database: {}", e.to_string());
-                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
             }
         };
 
-        match db.read_artifact(&repository, &channel, &release, artifact) {
-            Ok(a) => {
-                let file_path = state.config.data_dir().join("./".to_string() + a.path());
-                let mut file = match File::open(&file_path) {
-                    Ok(file) => file,
-                    Err(e) => {
-                        warn!("Failed to open artifact file in API path /{}/{}/{}/{}/download; real path {}: {}", repository, channel, release, artifact, file_path.to_str().unwrap(), e.to_string());
-                        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
-                    }
-                };
-                let mut data = Vec::new();
-                match file.read_to_end(&mut data) {
-                    Ok(_) => {
-                        return Response::new(data.into());
+        match query.mode {
+            DeleteMode::Yank => match db.yank_release(repository, channel, release, query.reason) {
+                Ok(release) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
+                Err(e) => db_error_response(e)
+            },
+            DeleteMode::Hard => {
+                let result = db.delete_release(repository, channel, release).map_err(db_error_response);
+                drop(db);
+                match result {
+                    Ok(paths) => {
+                        for path in paths {
+                            storage::remove_file(state.config.data_dir(), &path).await;
+                        }
+                        (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::None }))
                     },
-                    Err(e) => {
-                        warn!("Failed to read artifact file in API path /{}/{}/{}/{}/download; real path {}: {}", repository, channel, release, artifact, file_path.to_str().unwrap(), e.to_string());
-                        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
-                    }
+                    Err(response) => response
                 }
-            },
-            Err(e) => {
-                return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) })).into_response();
             }
         }
     }
-}
 
-#[derive(Serialize, Deserialize, Clone)]
-struct ResponseJson {
-    response_code: u8,
-    data: ResponseData
-}
+    async fn post_repository_channel_release_bundle(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>,
+        multipart: Result<Multipart, MultipartRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let multipart = match multipart {
+            Ok(multipart) => multipart,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
 
-#[derive(Serialize, Deserialize, Clone)]
-#[serde(untagged)]
-enum ResponseData {
-    None,
-    Error(String),
-    Repository(Repository),
-    Channel(Channel),
-    Release(Release),
-    Artifact(Artifact)
-}
\ No newline at end of file
+        match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => {
+                if let Err(e) = db.read_channel(&repository, &channel, true, true) {
+                    return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+                }
+                if db.read_release(&repository, &channel, &release, true, true).is_ok() {
+                    return (StatusCode::CONFLICT, Json(ResponseJson { response_code: 5, data: ResponseData::Error(DbError::KeyExists.to_string()) }))
+                }
+            },
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let (manifest, mut files) = match Self::receive_bundle(&state, multipart, &repository, &channel, &release).await {
+            Ok(bundle) => bundle,
+            Err(response) => return response
+        };
+
+        let mut artifacts = Vec::new();
+        for artifact in manifest.artifacts {
+            match files.iter().find(|(field, _, _)| *field == artifact.file) {
+                Some((_, path, (size, hashes))) => artifacts.push(StoredArtifact { name: artifact.name, path: path.clone(), artifact_type: artifact.artifact_type, size: *size, hashes: hashes.clone() }),
+                None => {
+                    for (_, path, _) in &files {
+                        storage::remove_file(state.config.data_dir(), path).await;
+                    }
+                    return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(format!("Missing file `{}`", artifact.file)) }))
+                }
+            }
+        }
+
+        let mut unused_files = Vec::new();
+        files.retain(|(field, path, _)| {
+            let used = artifacts.iter().any(|a| a.path == *path);
+            if !used {
+                warn!("Ignoring bundle file `{}` not referenced by the manifest", field);
+                unused_files.push(path.clone());
+            }
+            used
+        });
+        for path in unused_files {
+            storage::remove_file(state.config.data_dir(), &path).await;
+        }
+
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.create_release_bundle(repository, channel, release, manifest.name, manifest.created_at, artifacts).map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+
+        match result {
+            Ok(release) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
+            Err(response) => {
+                for (_, path, _) in &files {
+                    storage::remove_file(state.config.data_dir(), path).await;
+                }
+                response
+            }
+        }
+    }
+
+    /// Reads a release bundle from a multipart body. The `manifest` field holds
+    /// the JSON or TOML release description, every other file field is stored
+    /// under the data directory. Returns the manifest and the field name, path,
+    /// size and checksums of each stored file; nothing is left on disk on failure.
+    #[allow(clippy::result_large_err)]
+    async fn receive_bundle(state: &Api, mut multipart: Multipart, repository: &str, channel: &str, release: &str) -> Result<(ReleaseManifest, Vec<(String, String, (u64, Hashes))>), (StatusCode, Json<ResponseJson>)> {
+        let mut manifest = None;
+        let mut files: Vec<(String, String, (u64, Hashes))> = Vec::new();
+
+        let result = loop {
+            let field = match multipart.next_field().await {
+                Ok(Some(field)) => field,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) })))
+            };
+            let Some(field_name) = field.name().map(|n| n.to_string()) else {
+                continue;
+            };
+
+            if field_name == "manifest" {
+                match field.text().await {
+                    Ok(text) => match ReleaseManifest::parse(&text) {
+                        Ok(m) => manifest = Some(m),
+                        Err(e) => break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e) })))
+                    },
+                    Err(e) => break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) })))
+                }
+            } else if files.iter().any(|(f, _, _)| *f == field_name) {
+                break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(format!("Duplicate file `{}`", field_name)) })));
+            } else {
+                let Some(path) = storage::new_artifact_path(repository, channel, release) else {
+                    break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(DbError::InvalidKey.to_string()) })));
+                };
+                match storage::write_field(state.config.data_dir(), &path, field).await {
+                    Ok(file) => files.push((field_name, path, file)),
+                    Err(e) => {
+                        warn!("Failed to write uploaded file {}: {}", path, e.to_string());
+                        break Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })));
+                    }
+                }
+            }
+        };
+
+        let result = result.and_then(|_| match manifest {
+            Some(manifest) => Ok(manifest),
+            None => Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Missing field `manifest`".to_string()) })))
+        });
+
+        match result {
+            Ok(manifest) => Ok((manifest, files)),
+            Err(response) => {
+                for (_, path, _) in &files {
+                    storage::remove_file(state.config.data_dir(), path).await;
+                }
+                Err(response)
+            }
+        }
+    }
+
+    async fn post_repository_channel_release_publish(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.publish_release(repository, channel, release) {
+            Ok(release) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn post_repository_channel_release_promote(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>,
+        query: Result<Query<PromoteReleaseQuery>, QueryRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Query(query) = match query {
+            Ok(query) => query,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        if !token.allows(Some(&repository), Some(&query.to), Action::Promote) {
+            return forbidden_response(Action::Promote)
+        }
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.promote_release(repository, channel, release, query.to) {
+            Ok(release) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Release(release) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn post_repository_channel_release_artifacts(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>,
+        multipart: Result<Multipart, MultipartRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let multipart = match multipart {
+            Ok(multipart) => multipart,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => {
+                if let Err(e) = db.read_release(&repository, &channel, &release, true, true) {
+                    return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+                }
+            },
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let path = match storage::new_artifact_path(&repository, &channel, &release) {
+            Some(path) => path,
+            None => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(DbError::InvalidKey.to_string()) }))
+        };
+        let (name, artifact_type, (size, hashes)) = match Self::receive_artifact_upload(&state, multipart, &path).await {
+            Ok(upload) => match (upload.name.or(upload.file_name), upload.artifact_type) {
+                (Some(name), Some(artifact_type)) => (name, artifact_type, upload.file),
+                (name, _) => {
+                    let field = if name.is_none() { "name" } else { "type" };
+                    storage::remove_file(state.config.data_dir(), &path).await;
+                    return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(format!("Missing field `{}`", field)) }))
+                }
+            },
+            Err(response) => return response
+        };
+
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.create_artifact(repository, channel, release, name, path.clone(), artifact_type, size, hashes).map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+
+        match result {
+            Ok(artifact) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Artifact(artifact) })),
+            Err(response) => {
+                storage::remove_file(state.config.data_dir(), &path).await;
+                response
+            }
+        }
+    }
+
+    /// Reads an artifact upload from a multipart body, storing its `file` field
+    /// at `path` under the data directory. The `name` and `type` fields are
+    /// optional. Nothing is left on disk on failure.
+    #[allow(clippy::result_large_err)]
+    async fn receive_artifact_upload(state: &Api, mut multipart: Multipart, path: &str) -> Result<ArtifactUpload, (StatusCode, Json<ResponseJson>)> {
+        let mut name = None;
+        let mut artifact_type = None;
+        let mut file_name = None;
+        let mut file = None;
+
+        let result = loop {
+            let field = match multipart.next_field().await {
+                Ok(Some(field)) => field,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) })))
+            };
+
+            match field.name() {
+                Some("file") if file.is_none() => {
+                    file_name = field.file_name().map(|n| n.to_string());
+                    match storage::write_field(state.config.data_dir(), path, field).await {
+                        Ok(written) => file = Some(written),
+                        Err(e) => {
+                            warn!("Failed to write uploaded file {}: {}", path, e.to_string());
+                            break Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })));
+                        }
+                    }
+                },
+                Some("name") => match field.text().await {
+                    Ok(text) => name = Some(text),
+                    Err(e) => break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) })))
+                },
+                Some("type") => match field.text().await.ok().and_then(|text| text.parse::<ArtifactType>().ok()) {
+                    Some(t) => artifact_type = Some(t),
+                    None => break Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Invalid artifact type".to_string()) })))
+                },
+                _ => {}
+            }
+        };
+
+        let file_written = file.is_some();
+        let result = match file {
+            Some(file) => result.map(|_| ArtifactUpload { name, file_name, artifact_type, file }),
+            None => result.and(Err((StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Missing field `file`".to_string()) }))))
+        };
+
+        if result.is_err() && file_written {
+            storage::remove_file(state.config.data_dir(), path).await;
+        }
+        result
+    }
+
+    async fn post_repository_channel_release_uploads(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release)): Path<(String, String, String)>,
+        body: Result<Json<CreateUploadJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+        if body.sha256.len() != 64 || !body.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Invalid field `sha256`".to_string()) }))
+        }
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.create_upload_session(repository, channel, release, body.name, body.artifact_type, body.size, body.sha256, state.config.upload_session_ttl()) {
+            Ok(session) => (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::UploadSession(session) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn get_repository_channel_release_upload(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, session)): Path<(String, String, String, String)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.read_upload_session(repository, channel, release, session) {
+            Ok(session) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::UploadSession(session) })),
+            Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+        }
+    }
+
+    async fn delete_repository_channel_release_upload(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, session)): Path<(String, String, String, String)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.read_upload_session(&repository, &channel, &release, &session)
+                .and_then(|session| db.delete_upload_session(session.id()))
+                .map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                storage::remove_upload(state.config.data_dir(), &session).await;
+                (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::None }))
+            },
+            Err(response) => response
+        }
+    }
+
+    async fn put_repository_channel_release_upload_chunk(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, session, chunk)): Path<(String, String, String, String, u32)>,
+        headers: HeaderMap,
+        data: Bytes
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let expected_sha256 = match headers.get("X-Chunk-Sha256").and_then(|v| v.to_str().ok()) {
+            Some(sha256) => sha256.to_ascii_lowercase(),
+            None => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Missing header `X-Chunk-Sha256`".to_string()) }))
+        };
+        let sha256 = hex::encode(Sha256::digest(&data));
+        if sha256 != expected_sha256 {
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 9, data: ResponseData::Error(format!("Checksum mismatch: received chunk has SHA-256 {}", sha256)) }))
+        }
+
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.read_upload_session(&repository, &channel, &release, &session).map(|_| ()).map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+        if let Err(response) = result {
+            return response
+        }
+
+        if let Err(e) = storage::write_upload_chunk(state.config.data_dir(), &session, chunk, &data).await {
+            warn!("Failed to write chunk {} of upload session {}: {}", chunk, session, e.to_string());
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+        }
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.add_upload_chunk(repository, channel, release, session, chunk, data.len() as u64, sha256, state.config.upload_session_ttl()) {
+            Ok(session) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::UploadSession(session) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn post_repository_channel_release_upload_finalize(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, session)): Path<(String, String, String, String)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.read_upload_session(&repository, &channel, &release, &session).map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+        let session = match result {
+            Ok(session) => session,
+            Err(response) => return response
+        };
+
+        if session.chunks().is_empty() {
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Upload session has no chunks".to_string()) }))
+        }
+        if let Some(missing) = (0..).zip(session.chunks()).find(|(expected, chunk)| expected != *chunk).map(|(expected, _)| expected) {
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(format!("Missing chunk {}", missing)) }))
+        }
+
+        let path = match storage::new_artifact_path(&repository, &channel, &release) {
+            Some(path) => path,
+            None => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(DbError::InvalidKey.to_string()) }))
+        };
+        let (size, hashes) = match storage::assemble_upload(state.config.data_dir(), session.id(), session.chunks(), &path).await {
+            Ok(assembled) => assembled,
+            Err(e) => {
+                warn!("Failed to assemble upload session {}: {}", session.id(), e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+        let sha256 = hashes.sha256().unwrap_or_default();
+        if sha256 != session.sha256() || session.size().is_some_and(|s| s != size) {
+            storage::remove_file(state.config.data_dir(), &path).await;
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 9, data: ResponseData::Error(format!("Checksum mismatch: assembled file has SHA-256 {} and size {}", sha256, size)) }))
+        }
+
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.finalize_upload_session(repository, channel, release, session.id().to_string(), path.clone(), size, hashes).map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+
+        match result {
+            Ok(artifact) => {
+                storage::remove_upload(state.config.data_dir(), session.id()).await;
+                (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::Artifact(artifact) }))
+            },
+            Err(response) => {
+                storage::remove_file(state.config.data_dir(), &path).await;
+                response
+            }
+        }
+    }
+
+    async fn get_repository_channel_release_artifact(
+        State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
+        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let include_private = include_private(&token, &repository, &channel);
+        let include_drafts = include_drafts(&token, &repository, &channel);
+        match db.read_artifact(repository, channel, release, artifact, include_private, include_drafts) {
+            Ok(artifact) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Artifact(artifact) })),
+            Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+        }
+    }
+
+    async fn patch_repository_channel_release_artifact(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>,
+        body: Result<Json<UpdateArtifactJson>, JsonRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Json(body) = match body {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+        if body.content_type.as_ref().is_some_and(|t| !t.is_empty() && (!t.contains('/') || HeaderValue::from_str(t).is_err())) {
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error("Invalid field `content_type`".to_string()) }))
+        }
+
+        let db = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        match db.update_artifact(repository, channel, release, artifact, body.name, body.artifact_type, body.content_type) {
+            Ok(artifact) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Artifact(artifact) })),
+            Err(e) => db_error_response(e)
+        }
+    }
+
+    async fn put_repository_channel_release_artifact_file(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>,
+        multipart: Result<Multipart, MultipartRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let multipart = match multipart {
+            Ok(multipart) => multipart,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => {
+                if let Err(e) = db.read_artifact(&repository, &channel, &release, artifact, true, true) {
+                    return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+                }
+            },
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let path = match storage::new_artifact_path(&repository, &channel, &release) {
+            Some(path) => path,
+            None => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(DbError::InvalidKey.to_string()) }))
+        };
+        let upload = match Self::receive_artifact_upload(&state, multipart, &path).await {
+            Ok(upload) => upload,
+            Err(response) => return response
+        };
+
+        let (size, hashes) = upload.file;
+        let result = match ReleaseDatabase::new(state.config.db_path()).map(|db| db.with_actor(&token)) {
+            Ok(db) => db.replace_artifact_file(repository, channel, release, artifact, path.clone(), size, hashes, upload.name, upload.artifact_type).map_err(db_error_response),
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })))
+            }
+        };
+
+        match result {
+            Ok(artifact) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::Artifact(artifact) })),
+            Err(response) => {
+                storage::remove_file(state.config.data_dir(), &path).await;
+                response
+            }
+        }
+    }
+
+    async fn get_repository_channel_release_artifact_revisions(
+        State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
+        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let include_private = include_private(&token, &repository, &channel);
+        let include_drafts = include_drafts(&token, &repository, &channel);
+        match db.read_artifact_revisions(repository, channel, release, artifact, include_private, include_drafts) {
+            Ok(revisions) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::ArtifactRevisions(revisions) })),
+            Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+        }
+    }
+
+    async fn get_repository_channel_release_artifact_revision(
+        State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
+        Path((repository, channel, release, artifact, revision)): Path<(String, String, String, u32, u32)>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let include_private = include_private(&token, &repository, &channel);
+        let include_drafts = include_drafts(&token, &repository, &channel);
+        match db.read_artifact_revision(repository, channel, release, artifact, revision, include_private, include_drafts) {
+            Ok(revision) => (StatusCode::OK, Json(ResponseJson { response_code: 0, data: ResponseData::ArtifactRevision(revision) })),
+            Err(e) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+        }
+    }
+
+    async fn get_repository_channel_release_artifact_download(
+        State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
+        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>,
+        query: Result<Query<SignedDownloadQuery>, QueryRejection>,
+        headers: HeaderMap
+    ) -> Response<body::Body> {
+        let Query(query) = match query {
+            Ok(query) => query,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) })).into_response()
+        };
+
+        let api_path = format!("/{}/{}/{}/{}/download", repository, channel, release, artifact);
+        // Signatures are only issued for artifacts the signing token could see.
+        let signed = match (query.expires, query.signature, state.config.download_secret()) {
+            (None, None, _) => false,
+            (Some(expires), Some(signature), Some(secret)) if expires >= unix_time() && auth::verify_download(secret, &api_path, expires, &signature) => true,
+            _ => return unauthorized_response("Invalid or expired download signature")
+        };
+
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
+            }
+        };
+
+        let include_private = signed || include_private(&token, &repository, &channel);
+        let include_drafts = signed || include_drafts(&token, &repository, &channel);
+        let artifact = match db.read_artifact(&repository, &channel, &release, artifact, include_private, include_drafts) {
+            Ok(a) => a,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) })).into_response()
+            }
+        };
+        drop(db);
+
+        Self::file_response(&state, artifact.path(), artifact.hashes(), artifact.name(), artifact.content_type(), &api_path, &headers).await
+    }
+
+    /// Mints a download URL for an artifact that works without a token until it expires.
+    async fn post_repository_channel_release_artifact_sign(
+        State(state): State<Arc<Api>>,
+        Extension(token): Extension<Token>,
+        Path((repository, channel, release, artifact)): Path<(String, String, String, u32)>,
+        query: Result<Query<SignDownloadQuery>, QueryRejection>
+    ) -> (StatusCode, Json<ResponseJson>) {
+        let Query(query) = match query {
+            Ok(query) => query,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.body_text()) }))
+        };
+
+        let Some(secret) = state.config.download_secret() else {
+            warn!("Refusing to sign download URL: no download secret configured");
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error("Signed download URLs are not configured".to_string()) }))
+        };
+        let expires_in = query.expires_in.unwrap_or(60 * 60).min(state.config.max_download_url_ttl());
+
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+            }
+        };
+
+        let include_drafts = token.allows(Some(&repository), Some(&channel), Action::Publish);
+        if let Err(e) = db.read_artifact(&repository, &channel, &release, artifact, true, include_drafts) {
+            return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) }))
+        }
+
+        let api_path = format!("/{}/{}/{}/{}/download", repository, channel, release, artifact);
+        let expires_at = unix_time() + expires_in;
+        let signature = auth::sign_download(secret, &api_path, expires_at);
+        let signed_url = SignedUrlJson {
+            url: format!("{api_path}?expires={expires_at}&signature={signature}"),
+            expires_at
+        };
+        (StatusCode::CREATED, Json(ResponseJson { response_code: 0, data: ResponseData::SignedUrl(signed_url) }))
+    }
+
+    async fn get_repository_channel_release_artifact_revision_download(
+        State(state): State<Arc<Api>>,
+        token: Option<Extension<Token>>,
+        Path((repository, channel, release, artifact, revision)): Path<(String, String, String, u32, u32)>,
+        headers: HeaderMap
+    ) -> Response<body::Body> {
+        let db = match ReleaseDatabase::new(state.config.db_path()) {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Failed to open database: {}", e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
+            }
+        };
+
+        let include_private = include_private(&token, &repository, &channel);
+        let include_drafts = include_drafts(&token, &repository, &channel);
+        let artifact_revision = match db.read_artifact_revision(&repository, &channel, &release, artifact, revision, include_private, include_drafts) {
+            Ok(r) => r,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) })).into_response()
+            }
+        };
+        drop(db);
+
+        Self::file_response(&state, artifact_revision.path(), artifact_revision.hashes(), artifact_revision.name(), artifact_revision.content_type(), &format!("/{}/{}/{}/{}/revisions/{}/download", repository, channel, release, artifact, revision), &headers).await
+    }
+
+    /// Responds with the contents of the file at `path` under the data directory,
+    /// or the parts of it asked for with a `Range` header. The file is streamed
+    /// from disk rather than read into memory first. Its SHA-256, if known, is
+    /// used as a strong ETag and its checksums are sent as digests, and browsers
+    /// are told to save it as `name`.
+    async fn file_response(state: &Api, path: &str, hashes: &Hashes, name: &str, content_type: &str, api_path: &str, headers: &HeaderMap) -> Response<body::Body> {
+        let file_path = storage::file_path(state.config.data_dir(), path);
+        let metadata = match tokio::fs::metadata(&file_path).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Failed to open artifact file in API path {}; real path {}: {}", api_path, file_path.to_str().unwrap(), e.to_string());
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
+            }
+        };
+        let len = metadata.len();
+        let etag = hashes.sha256().map(conditional::strong_etag);
+        let last_modified = metadata.modified().ok();
+
+        // A stale `If-Range` means the client's partial copy is outdated, so it gets the whole file.
+        let if_range_matches = match headers.get(IF_RANGE) {
+            Some(if_range) => if_range.to_str().ok().zip(last_modified).is_some_and(|(if_range, modified)| conditional::if_range_matches(if_range, etag.as_deref(), modified)),
+            None => true
+        };
+        let ranges = match headers.get(RANGE).and_then(|h| h.to_str().ok()) {
+            Some(range) if if_range_matches => range::parse_range(range, len),
+            _ => ByteRanges::Full
+        };
+
+        let response = match ranges {
+            ByteRanges::Full => tokio::fs::File::open(&file_path).await.map(|file| {
+                (
+                    [(ACCEPT_RANGES, "bytes".to_string()), (CONTENT_TYPE, content_type.to_string()), (CONTENT_LENGTH, len.to_string())],
+                    body::Body::from_stream(ReaderStream::new(file))
+                ).into_response()
+            }),
+            ByteRanges::Partial(ranges) if ranges.len() == 1 => range::read_range(&file_path, &ranges[0]).await.map(|reader| {
+                let range = &ranges[0];
+                (
+                    StatusCode::PARTIAL_CONTENT,
+                    [
+                        (ACCEPT_RANGES, "bytes".to_string()),
+                        (CONTENT_TYPE, content_type.to_string()),
+                        (CONTENT_RANGE, format!("bytes {}-{}/{}", range.start(), range.end(), len)),
+                        (CONTENT_LENGTH, (range.end() - range.start() + 1).to_string())
+                    ],
+                    body::Body::from_stream(ReaderStream::new(reader))
+                ).into_response()
+            }),
+            ByteRanges::Partial(ranges) => {
+                let boundary = hex::encode(rand::random::<[u8; 16]>());
+                range::read_ranges(&file_path, &ranges, len, content_type, &boundary).await.map(|(reader, body_len)| {
+                    (
+                        StatusCode::PARTIAL_CONTENT,
+                        [
+                            (ACCEPT_RANGES, "bytes".to_string()),
+                            (CONTENT_TYPE, format!("multipart/byteranges; boundary={boundary}")),
+                            (CONTENT_LENGTH, body_len.to_string())
+                        ],
+                        body::Body::from_stream(ReaderStream::new(reader))
+                    ).into_response()
+                })
+            },
+            ByteRanges::Unsatisfiable => return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(ACCEPT_RANGES, "bytes".to_string()), (CONTENT_RANGE, format!("bytes */{len}"))],
+                Json(ResponseJson { response_code: 13, data: ResponseData::Error("Range not satisfiable".to_string()) })
+            ).into_response()
+        };
+
+        match response {
+            Ok(mut response) => {
+                if let Ok(content_disposition) = HeaderValue::from_str(&content_disposition(name)) {
+                    response.headers_mut().insert(CONTENT_DISPOSITION, content_disposition);
+                }
+                if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+                    response.headers_mut().insert(ETAG, etag);
+                }
+                if let Some((repr_digest, digest)) = digest_headers(hashes) {
+                    response.headers_mut().insert(REPR_DIGEST, repr_digest);
+                    response.headers_mut().insert(DIGEST, digest);
+                }
+                if let Some(last_modified) = last_modified.and_then(|modified| HeaderValue::from_str(&httpdate::fmt_http_date(modified)).ok()) {
+                    response.headers_mut().insert(LAST_MODIFIED, last_modified);
+                }
+                response
+            },
+            Err(e) => {
+                warn!("Failed to read artifact file in API path {}; real path {}: {}", api_path, file_path.to_str().unwrap(), e.to_string());
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) })).into_response()
+            }
+        }
+    }
+}
+
+fn cors_layer(cors: &Cors) -> CorsLayer {
+    let allowed_origins = if cors.allowed_origins().iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(cors.allowed_origins().iter().filter_map(|o| o.parse().ok()))
+    };
+
+    CorsLayer::new()
+        .allow_origin(allowed_origins)
+        .allow_methods(cors.allowed_methods().iter().filter_map(|m| m.parse::<Method>().ok()).collect::<Vec<_>>())
+        .allow_headers(cors.allowed_headers().iter().filter_map(|h| h.parse::<HeaderName>().ok()).collect::<Vec<_>>())
+        .expose_headers(cors.exposed_headers().iter().filter_map(|h| h.parse::<HeaderName>().ok()).collect::<Vec<_>>())
+        .max_age(Duration::from_secs(cors.max_age()))
+}
+
+/// `Repr-Digest` and `Digest` headers for a file with the given checksums, if
+/// any of the ones they support are known.
+fn digest_headers(hashes: &Hashes) -> Option<(HeaderValue, HeaderValue)> {
+    let digests: Vec<_> = [("sha-256", hashes.sha256()), ("sha-512", hashes.sha512())].into_iter()
+        .filter_map(|(algorithm, hash)| Some((algorithm, BASE64_STANDARD.encode(hex::decode(hash?).ok()?))))
+        .collect();
+    if digests.is_empty() {
+        return None
+    }
+
+    let repr_digest: Vec<_> = digests.iter().map(|(algorithm, digest)| format!("{algorithm}=:{digest}:")).collect();
+    let digest: Vec<_> = digests.iter().map(|(algorithm, digest)| format!("{}={digest}", algorithm.to_ascii_uppercase())).collect();
+    Some((HeaderValue::from_str(&repr_digest.join(", ")).ok()?, HeaderValue::from_str(&digest.join(",")).ok()?))
+}
+
+/// `Content-Disposition` telling browsers to save a download as `name`. Names
+/// that aren't plain ASCII are also given percent-encoded, with an ASCII
+/// approximation for clients that don't understand that.
+fn content_disposition(name: &str) -> String {
+    let fallback: String = name.chars()
+        .map(|c| if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    if fallback == name {
+        return format!("attachment; filename=\"{fallback}\"")
+    }
+
+    let encoded: String = name.bytes()
+        .map(|b| if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) { (b as char).to_string() } else { format!("%{b:02X}") })
+        .collect();
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Address of the client that sent a request. If the request came through
+/// trusted proxies, this is the last address in `X-Forwarded-For` that wasn't
+/// added by one of them. If a proxy forwarded something that isn't an address,
+/// the client is unknown, rather than taken to be the proxy itself.
+fn client_addr(trusted_proxies: &[IpNet], peer: SocketAddr, headers: &HeaderMap) -> Option<IpAddr> {
+    let is_trusted = |addr: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(addr));
+
+    let mut addr = peer.ip();
+    if !is_trusted(&addr) {
+        return Some(addr);
+    }
+
+    let mut forwarded = Vec::new();
+    for header in headers.get_all(X_FORWARDED_FOR) {
+        forwarded.extend(header.to_str().ok()?.split(','));
+    }
+    for hop in forwarded.into_iter().rev() {
+        addr = parse_forwarded_addr(hop.trim())?;
+        if !is_trusted(&addr) {
+            break;
+        }
+    }
+
+    Some(addr)
+}
+
+/// Parses an address from `X-Forwarded-For`, which some proxies send with a
+/// port, and IPv6 addresses then in brackets.
+fn parse_forwarded_addr(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>().ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// Private channels are only visible to tokens that may read them.
+fn include_private(token: &Option<Extension<Token>>, repository: &str, channel: &str) -> bool {
+    token.as_ref().is_some_and(|Extension(token)| token.allows(Some(repository), Some(channel), Action::Read))
+}
+
+/// Drafts are only visible to tokens that may publish to their channel.
+fn include_drafts(token: &Option<Extension<Token>>, repository: &str, channel: &str) -> bool {
+    token.as_ref().is_some_and(|Extension(token)| token.allows(Some(repository), Some(channel), Action::Publish))
+}
+
+fn forbidden_response(action: Action) -> (StatusCode, Json<ResponseJson>) {
+    (StatusCode::FORBIDDEN, Json(ResponseJson { response_code: 11, data: ResponseData::Error(format!("Token has no {action} grant here")) }))
+}
+
+fn unauthorized_response(message: &str) -> Response<body::Body> {
+    (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")], Json(ResponseJson { response_code: 10, data: ResponseData::Error(message.to_string()) })).into_response()
+}
+
+/// Maps an error returned by a [`ReleaseDatabase`] write to a response.
+fn db_error_response(e: Box<dyn Error>) -> (StatusCode, Json<ResponseJson>) {
+    match e.downcast_ref::<DbError>() {
+        Some(DbError::KeyExists) => (StatusCode::CONFLICT, Json(ResponseJson { response_code: 5, data: ResponseData::Error(e.to_string()) })),
+        Some(DbError::NotEmpty) => (StatusCode::CONFLICT, Json(ResponseJson { response_code: 6, data: ResponseData::Error(e.to_string()) })),
+        Some(DbError::Yanked) => (StatusCode::CONFLICT, Json(ResponseJson { response_code: 7, data: ResponseData::Error(e.to_string()) })),
+        Some(DbError::Unpublished) => (StatusCode::CONFLICT, Json(ResponseJson { response_code: 8, data: ResponseData::Error(e.to_string()) })),
+        Some(_) => (StatusCode::BAD_REQUEST, Json(ResponseJson { response_code: 4, data: ResponseData::Error(e.to_string()) })),
+        None => {
+            warn!("Failed to write to database: {}", e.to_string());
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseJson { response_code: 100, data: ResponseData::Error(e.to_string()) }))
+        }
+    }
+}
+
+/// Body of requests that create or rename a repository.
+#[derive(Deserialize)]
+struct KeyJson {
+    id: String
+}
+
+#[derive(Deserialize)]
+struct CreateChannelJson {
+    id: String,
+    #[serde(default)]
+    private: bool
+}
+
+#[derive(Deserialize)]
+struct UpdateChannelJson {
+    id: Option<String>,
+    private: Option<bool>
+}
+
+#[derive(Deserialize)]
+struct CreateReleaseJson {
+    id: String,
+    name: String,
+    created_at: Option<u64>
+}
+
+#[derive(Deserialize)]
+struct UpdateReleaseJson {
+    name: Option<String>,
+    created_at: Option<u64>
+}
+
+#[derive(Deserialize)]
+struct UpdateArtifactJson {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    artifact_type: Option<ArtifactType>,
+    /// Media type to serve the file as instead of the artifact type's. An empty
+    /// string removes the override.
+    content_type: Option<String>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum DeleteMode {
+    /// Hide the release from listings but keep it readable by id.
+    #[default]
+    Yank,
+    /// Remove the release, its artifacts and their files.
+    Hard
+}
+
+#[derive(Deserialize)]
+struct DeleteReleaseQuery {
+    #[serde(default)]
+    mode: DeleteMode,
+    reason: Option<String>
+}
+
+#[derive(Deserialize)]
+struct PromoteReleaseQuery {
+    to: String
+}
+
+#[derive(Deserialize)]
+struct SignDownloadQuery {
+    /// Seconds until the URL expires, capped at the configured maximum.
+    expires_in: Option<u64>
+}
+
+#[derive(Deserialize)]
+struct SignedDownloadQuery {
+    expires: Option<u64>,
+    signature: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SignedUrlJson {
+    /// Path and query of the download, relative to the API root.
+    url: String,
+    expires_at: u64
+}
+
+/// Release description sent along with the files of a bundle.
+#[derive(Deserialize)]
+struct ReleaseManifest {
+    name: String,
+    created_at: Option<u64>,
+    #[serde(default)]
+    artifacts: Vec<ManifestArtifact>
+}
+
+#[derive(Deserialize)]
+struct ManifestArtifact {
+    name: String,
+    #[serde(rename = "type")]
+    artifact_type: ArtifactType,
+    /// Name of the multipart field holding the artifact's file.
+    file: String
+}
+
+impl ReleaseManifest {
+    /// Parses a manifest written in either JSON or TOML. A TOML document can't
+    /// start with `{`, so that tells the two apart regardless of how the client
+    /// labelled the field.
+    fn parse(text: &str) -> Result<Self, String> {
+        if text.trim_start().starts_with('{') {
+            serde_json::from_str(text).map_err(|e| format!("Invalid manifest, expected a JSON or TOML document (read as JSON): {}", e))
+        } else {
+            toml::from_str(text).map_err(|e| format!("Invalid manifest, expected a JSON or TOML document (read as TOML): {}", e))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateUploadJson {
+    name: String,
+    #[serde(rename = "type")]
+    artifact_type: ArtifactType,
+    size: Option<u64>,
+    /// SHA-256 of the complete file, checked when the upload is finalized.
+    sha256: String
+}
+
+struct ArtifactUpload {
+    name: Option<String>,
+    /// Name of the uploaded file as sent by the client.
+    file_name: Option<String>,
+    artifact_type: Option<ArtifactType>,
+    /// Size and checksums of the stored file.
+    file: (u64, Hashes)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ResponseJson {
+    response_code: u8,
+    data: ResponseData
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum ResponseData {
+    None,
+    Error(String),
+    Repository(Repository),
+    Channel(Channel),
+    Release(Release),
+    Artifact(Artifact),
+    ArtifactRevision(ArtifactRevision),
+    ArtifactRevisions(Vec<ArtifactRevision>),
+    UploadSession(UploadSession),
+    AuditLog(Vec<AuditEntry>),
+    SignedUrl(SignedUrlJson)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROXY: &str = "10.0.0.1:443";
+
+    fn forwarded_for(values: &[&[u8]]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(X_FORWARDED_FOR, HeaderValue::from_bytes(value).unwrap());
+        }
+        headers
+    }
+
+    fn client(headers: &HeaderMap) -> Option<IpAddr> {
+        client_addr(&["10.0.0.0/8".parse().unwrap()], PROXY.parse().unwrap(), headers)
+    }
+
+    #[test]
+    fn untrusted_peer_is_the_client() {
+        let headers = forwarded_for(&[b"10.0.0.5"]);
+        assert_eq!(client_addr(&[], PROXY.parse().unwrap(), &headers), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(client(&forwarded_for(&[])), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn skips_trusted_proxies() {
+        assert_eq!(client(&forwarded_for(&[b"203.0.113.7"])), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(client(&forwarded_for(&[b"198.51.100.1, 203.0.113.7, 10.0.0.2"])), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(client(&forwarded_for(&[b"198.51.100.1", b"203.0.113.7", b"10.0.0.2"])), Some("203.0.113.7".parse().unwrap()));
+        // Everything left of the first untrusted hop may be made up by the client.
+        assert_eq!(client(&forwarded_for(&[b"garbage, 203.0.113.7"])), Some("203.0.113.7".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_hops_with_ports() {
+        assert_eq!(client(&forwarded_for(&[b"203.0.113.7:5678"])), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(client(&forwarded_for(&[b"[2001:db8::1]:5678"])), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(client(&forwarded_for(&[b"[2001:db8::1]"])), Some("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn unparsable_hops_make_the_client_unknown() {
+        assert_eq!(client(&forwarded_for(&[b"unknown"])), None);
+        assert_eq!(client(&forwarded_for(&[b"203.0.113.7, unknown"])), None);
+        assert_eq!(client(&forwarded_for(&[b"203.0.113.7,"])), None);
+        assert_eq!(client(&forwarded_for(&[b"\xff"])), None);
+        assert_eq!(client(&forwarded_for(&[b"203.0.113.7", b"\xff"])), None);
+    }
+
+    #[test]
+    fn parses_manifests_by_content() {
+        let json = ReleaseManifest::parse(r#"{ "name": "R1", "artifacts": [{ "name": "a", "type": "Other", "file": "f" }] }"#).unwrap();
+        assert_eq!((json.name.as_str(), json.artifacts.len()), ("R1", 1));
+        let toml = ReleaseManifest::parse("name = \"R1\"\n[[artifacts]]\nname = \"a\"\ntype = \"Other\"\nfile = \"f\"\n").unwrap();
+        assert_eq!((toml.name.as_str(), toml.artifacts.len()), ("R1", 1));
+        assert!(ReleaseManifest::parse("name: R1").is_err_and(|e| e.contains("JSON or TOML")));
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// Default and maximum number of entries returned by one audit log query.
+const MAX_ENTRIES: u32 = 500;
+
+/// A change made to the catalog, with the state of its target before and
+/// after it. The actor is the name of the token that made the change, and
+/// the token id is `None` for tokens that aren't stored, such as CI-issued
+/// JWTs. Both are `None` for changes made on the host itself.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    id: u64,
+    token: Option<u32>,
+    actor: Option<String>,
+    action: String,
+    target: String,
+    created_at: u64,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>
+}
+
+impl AuditEntry {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<S: Into<String>>(id: u64, token: Option<u32>, actor: Option<String>, action: S, target: S, created_at: u64, before: Option<serde_json::Value>, after: Option<serde_json::Value>) -> Self {
+        Self {
+            id,
+            token,
+            actor,
+            action: action.into(),
+            target: target.into(),
+            created_at,
+            before,
+            after
+        }
+    }
+}
+
+/// Narrows down an audit log query. Fields that are `None` match every entry.
+#[derive(Deserialize)]
+pub struct AuditFilter {
+    pub token: Option<u32>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    /// Matches the target and everything below it.
+    pub target: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    /// Only entries older than this entry id, for paging.
+    pub before: Option<u64>,
+    pub limit: Option<u32>
+}
+
+impl AuditFilter {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(MAX_ENTRIES).min(MAX_ENTRIES)
+    }
+}
@@ -0,0 +1,322 @@
+use std::{fmt::Display, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::db::is_valid_key;
+
+/// Matches any repository or channel in a grant.
+pub const WILDCARD: &str = "*";
+
+/// Prefix of every token secret, so leaked secrets are easy to recognise.
+const TOKEN_PREFIX: &str = "bta_";
+
+/// Credentials a request was made with. Tokens created through the CLI are
+/// stored and have an id, while tokens derived from a verified JWT only live
+/// as long as the request.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Token {
+    id: Option<u32>,
+    name: String,
+    created_at: u64,
+    revoked_at: Option<u64>,
+    grants: Vec<Grant>
+}
+
+impl Token {
+    pub fn new<S: Into<String>>(id: u32, name: S, created_at: u64, grants: Vec<Grant>) -> Self {
+        Self {
+            id: Some(id),
+            name: name.into(),
+            created_at,
+            revoked_at: None,
+            grants
+        }
+    }
+
+    /// A token that isn't stored in the database.
+    pub fn external<S: Into<String>>(name: S, created_at: u64, grants: Vec<Grant>) -> Self {
+        Self {
+            id: None,
+            name: name.into(),
+            created_at,
+            revoked_at: None,
+            grants
+        }
+    }
+
+    pub fn with_revoked_at(mut self, revoked_at: u64) -> Self {
+        self.revoked_at = Some(revoked_at);
+        self
+    }
+
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn revoked_at(&self) -> Option<u64> {
+        self.revoked_at
+    }
+
+    pub fn grants(&self) -> &Vec<Grant> {
+        &self.grants
+    }
+
+    /// Whether any of the token's grants allows `action`. Without a channel the
+    /// action applies to the whole repository, and without a repository to
+    /// the whole catalog, so only grants with wildcards for those cover it.
+    pub fn allows(&self, repository_id: Option<&str>, channel_id: Option<&str>, action: Action) -> bool {
+        self.grants.iter().any(|grant| grant.allows(repository_id, channel_id, action))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Grant {
+    repository: String,
+    channel: String,
+    action: Action
+}
+
+impl Grant {
+    pub fn new<S: Into<String>>(repository: S, channel: S, action: Action) -> Self {
+        Self {
+            repository: repository.into(),
+            channel: channel.into(),
+            action
+        }
+    }
+
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    fn allows(&self, repository_id: Option<&str>, channel_id: Option<&str>, action: Action) -> bool {
+        let covers = |granted: &str, requested: Option<&str>| {
+            granted == WILDCARD || requested.is_some_and(|requested| granted == requested)
+        };
+
+        covers(&self.repository, repository_id)
+            && covers(&self.channel, channel_id)
+            && self.action.implies(action)
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "{} ({})", id, self.name),
+            None => write!(f, "{}", self.name)
+        }
+    }
+}
+
+impl Display for Grant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}:{}", self.repository, self.channel, self.action)
+    }
+}
+
+/// Parses a grant written as `repository[/channel]:action`. Either key may be
+/// `*`, and leaving out the channel grants the action on every channel.
+impl FromStr for Grant {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keys, action) = s.rsplit_once(':').ok_or(())?;
+        let (repository, channel) = keys.split_once('/').unwrap_or((keys, WILDCARD));
+        if ![repository, channel].iter().all(|key| *key == WILDCARD || is_valid_key(key)) {
+            return Err(());
+        }
+
+        Ok(Self::new(repository, channel, action.parse()?))
+    }
+}
+
+/// Something a token may be allowed to do.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// See private channels.
+    Read,
+    /// Create, edit and see draft releases and their artifacts.
+    Publish,
+    /// Promote releases into a channel.
+    Promote,
+    /// Yank or delete releases.
+    Delete,
+    /// Create, rename and delete repositories and channels, and everything else.
+    Admin
+}
+
+impl Action {
+    /// Every action implies `Read`, and `Admin` implies every action.
+    fn implies(self, action: Action) -> bool {
+        self == action || self == Action::Admin || action == Action::Read
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Read => write!(f, "read"),
+            Action::Publish => write!(f, "publish"),
+            Action::Promote => write!(f, "promote"),
+            Action::Delete => write!(f, "delete"),
+            Action::Admin => write!(f, "admin")
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "publish" => Ok(Self::Publish),
+            "promote" => Ok(Self::Promote),
+            "delete" => Ok(Self::Delete),
+            "admin" => Ok(Self::Admin),
+            _ => Err(())
+        }
+    }
+}
+
+impl From<Action> for u32 {
+    fn from(value: Action) -> Self {
+        match value {
+            Action::Read => 0,
+            Action::Publish => 1,
+            Action::Promote => 2,
+            Action::Delete => 3,
+            Action::Admin => 4
+        }
+    }
+}
+
+impl TryFrom<u32> for Action {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Read),
+            1 => Ok(Self::Publish),
+            2 => Ok(Self::Promote),
+            3 => Ok(Self::Delete),
+            4 => Ok(Self::Admin),
+            _ => Err(())
+        }
+    }
+}
+
+/// Hashes a token secret for storage. Secrets are long and random, so they
+/// don't need a slow password hash.
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Generates a new random token secret.
+pub fn generate_secret() -> String {
+    format!("{TOKEN_PREFIX}{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+/// Signs a download URL path so it can be used without a token until `expires_at`.
+pub fn sign_download(secret: &str, path: &str, expires_at: u64) -> String {
+    hex::encode(download_mac(secret, path, expires_at).finalize().into_bytes())
+}
+
+/// Checks a signature made by [`sign_download`]. Expiry is checked separately.
+pub fn verify_download(secret: &str, path: &str, expires_at: u64, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => download_mac(secret, path, expires_at).verify_slice(&signature).is_ok(),
+        Err(_) => false
+    }
+}
+
+fn download_mac(secret: &str, path: &str, expires_at: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{path}\n{expires_at}").as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_with(scopes: &[&str]) -> Token {
+        Token::new(1, "test", 0, scopes.iter().map(|scope| scope.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn channel_grant_stays_in_its_channel() {
+        let token = token_with(&["mod/nightly:publish"]);
+        assert!(token.allows(Some("mod"), Some("nightly"), Action::Publish));
+        assert!(!token.allows(Some("mod"), Some("stable"), Action::Publish));
+        assert!(!token.allows(Some("mod"), Some("stable"), Action::Read));
+        assert!(!token.allows(Some("updater"), Some("nightly"), Action::Publish));
+        // Repository wide actions need the grant on every channel.
+        assert!(!token.allows(Some("mod"), None, Action::Publish));
+        assert!(!token.allows(None, None, Action::Publish));
+    }
+
+    #[test]
+    fn grant_without_channel_covers_every_channel() {
+        let token = token_with(&["mod:publish"]);
+        assert!(token.allows(Some("mod"), Some("nightly"), Action::Publish));
+        assert!(token.allows(Some("mod"), Some("stable"), Action::Publish));
+        assert!(token.allows(Some("mod"), None, Action::Publish));
+        assert!(!token.allows(Some("updater"), Some("release"), Action::Publish));
+        assert!(!token.allows(None, None, Action::Publish));
+    }
+
+    #[test]
+    fn wildcards_cover_any_key() {
+        let token = token_with(&["*/nightly:delete"]);
+        assert!(token.allows(Some("mod"), Some("nightly"), Action::Delete));
+        assert!(token.allows(Some("updater"), Some("nightly"), Action::Delete));
+        assert!(!token.allows(Some("mod"), Some("stable"), Action::Delete));
+
+        let token = token_with(&["*:admin"]);
+        assert!(token.allows(None, None, Action::Admin));
+        assert!(token.allows(Some("mod"), Some("nightly"), Action::Promote));
+    }
+
+    #[test]
+    fn actions_imply_read_and_admin_implies_all() {
+        for action in [Action::Read, Action::Publish, Action::Promote, Action::Delete, Action::Admin] {
+            assert!(action.implies(Action::Read));
+            assert!(Action::Admin.implies(action));
+            assert!(action.implies(action));
+        }
+        assert!(!Action::Read.implies(Action::Publish));
+        assert!(!Action::Publish.implies(Action::Promote));
+        assert!(!Action::Promote.implies(Action::Delete));
+        assert!(!Action::Delete.implies(Action::Admin));
+    }
+
+    #[test]
+    fn parses_grants() {
+        let grant: Grant = "mod:publish".parse().unwrap();
+        assert_eq!((grant.repository(), grant.channel(), grant.action()), ("mod", WILDCARD, Action::Publish));
+        assert!("mod/nightly:fly".parse::<Grant>().is_err());
+        assert!("mod/../x:read".parse::<Grant>().is_err());
+        assert!("mod/nightly".parse::<Grant>().is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::error::Error;
+
+use clap::{Parser, Subcommand};
+
+use crate::{auth::Grant, config::Config, db::ReleaseDatabase, storage};
+
+#[derive(Parser)]
+#[command(version, about = "RESTful API for distributing BTA! releases")]
+pub struct Cli {
+    /// Runs an admin command instead of starting the server.
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manages the tokens used to authenticate against the API.
+    #[command(subcommand)]
+    Token(TokenCommand),
+    /// Maintains stored artifact files.
+    #[command(subcommand)]
+    Artifact(ArtifactCommand)
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommand {
+    /// Creates a token and prints its secret.
+    Create {
+        /// Grant, given as `repository[/channel]:action`, where action is one of
+        /// read, publish, promote, delete or admin. May be repeated.
+        #[arg(long = "scope", required = true, value_parser = parse_grant)]
+        scopes: Vec<Grant>,
+        /// Name to recognise the token by. Defaults to its scopes.
+        #[arg(long)]
+        name: Option<String>
+    },
+    /// Lists all tokens, including revoked ones.
+    List,
+    /// Revokes a token so it can't be used anymore.
+    Revoke {
+        id: u32
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ArtifactCommand {
+    /// Computes the sizes and checksums of stored files that are missing them.
+    BackfillHashes
+}
+
+fn parse_grant(s: &str) -> Result<Grant, String> {
+    s.parse().map_err(|_| format!("invalid scope \"{s}\", expected repository[/channel]:action"))
+}
+
+/// Runs an admin command directly against the configured database.
+pub fn run(config: &Config, command: Command) -> Result<(), Box<dyn Error>> {
+    let db = ReleaseDatabase::new(config.db_path())?;
+
+    match command {
+        Command::Token(TokenCommand::Create { scopes, name }) => {
+            let name = name.unwrap_or_else(|| scopes.iter().map(Grant::to_string).collect::<Vec<_>>().join(","));
+            let (token, secret) = db.create_token(name, scopes)?;
+            // Only the secret goes to stdout, so it can be captured by scripts.
+            eprintln!("Created token {token}. Store its secret now, it can't be shown again:");
+            println!("{secret}");
+        },
+        Command::Token(TokenCommand::List) => {
+            for token in db.read_tokens()? {
+                let grants: Vec<_> = token.grants().iter().map(Grant::to_string).collect();
+                let status = match token.revoked_at() {
+                    Some(revoked_at) => format!("revoked at {revoked_at}"),
+                    None => "active".to_string()
+                };
+                println!("{}\t{}\tcreated at {}\t{}\t{}", token.id().unwrap_or_default(), token.name(), token.created_at(), status, grants.join(","));
+            }
+        },
+        Command::Token(TokenCommand::Revoke { id }) => {
+            let token = db.revoke_token(id)?;
+            eprintln!("Revoked token {token}");
+        },
+        Command::Artifact(ArtifactCommand::BackfillHashes) => {
+            let paths = db.read_paths_without_hashes()?;
+            let mut updated = 0;
+            for path in &paths {
+                // Files may have been removed by hand, which shouldn't stop the rest.
+                match storage::hash_file(config.data_dir(), path) {
+                    Ok((size, hashes)) => {
+                        db.set_file_hashes(path, size, &hashes)?;
+                        updated += 1;
+                    },
+                    Err(e) => eprintln!("Failed to read {path}: {e}")
+                }
+            }
+            eprintln!("Backfilled {updated} of {} files", paths.len());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::time::SystemTime;
+
+use axum::http::{header::{IF_MODIFIED_SINCE, IF_NONE_MATCH}, HeaderMap, HeaderValue};
+use sha2::{Digest, Sha256};
+
+/// Strong entity tag of a file, from its SHA-256.
+pub fn strong_etag(sha256: &str) -> String {
+    format!("\"{sha256}\"")
+}
+
+/// Weak entity tag of a generated response body. The body is only equivalent,
+/// not byte for byte identical, to others with the same tag, as serializing
+/// the same data again isn't guaranteed to give the same bytes.
+pub fn weak_etag(body: &[u8]) -> String {
+    format!("W/\"{}\"", hex::encode(Sha256::digest(body)))
+}
+
+/// Whether the client's copy of a response with the given `ETag` and
+/// `Last-Modified` is still current, going by the request's `If-None-Match`
+/// or, if it has none, `If-Modified-Since` header.
+pub fn is_not_modified(request_headers: &HeaderMap, etag: Option<&HeaderValue>, last_modified: Option<&HeaderValue>) -> bool {
+    if let Some(if_none_match) = request_headers.get(IF_NONE_MATCH) {
+        let (Ok(if_none_match), Some(Ok(etag))) = (if_none_match.to_str(), etag.map(HeaderValue::to_str)) else {
+            return false
+        };
+        return if_none_match.split(',').map(str::trim).any(|tag| tag == "*" || weak_eq(tag, etag))
+    }
+
+    match (request_headers.get(IF_MODIFIED_SINCE).and_then(parse_date), last_modified.and_then(parse_date)) {
+        (Some(if_modified_since), Some(last_modified)) => last_modified <= if_modified_since,
+        _ => false
+    }
+}
+
+/// Whether an `If-Range` header still matches a response with the given
+/// entity tag and modification time, so its `Range` header applies. Entity
+/// tags have to match strongly and dates exactly.
+pub fn if_range_matches(if_range: &str, etag: Option<&str>, modified: SystemTime) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') {
+        etag.is_some_and(|etag| !etag.starts_with("W/") && etag == if_range)
+    } else {
+        httpdate::parse_http_date(if_range).is_ok_and(|date| httpdate::fmt_http_date(date) == httpdate::fmt_http_date(modified))
+    }
+}
+
+/// Compares two entity tags, ignoring whether they are weak.
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+fn parse_date(value: &HeaderValue) -> Option<SystemTime> {
+    value.to_str().ok().and_then(|value| httpdate::parse_http_date(value).ok())
+}
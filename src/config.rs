@@ -1,13 +1,191 @@
-use std::{fs::File, io::Read, net::SocketAddr, path::{Path, PathBuf}, str::FromStr};
+use std::{collections::HashMap, error::Error, fs::File, io::{self, Read}, net::SocketAddr, path::{Path, PathBuf}, str::FromStr};
 
+use axum::http::{HeaderName, HeaderValue, Method};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 
+use crate::auth::Grant;
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     bind_addr: SocketAddr,
     db_path: PathBuf,
     data_dir: PathBuf,
     log_level: String,
+    #[serde(default = "default_max_upload_size")]
+    max_upload_size: usize,
+    #[serde(default = "default_seed_db")]
+    seed_db: bool,
+    #[serde(default = "default_upload_session_ttl")]
+    upload_session_ttl: u64,
+    #[serde(default)]
+    download_secret: Option<String>,
+    #[serde(default = "default_max_download_url_ttl")]
+    max_download_url_ttl: u64,
+    #[serde(default)]
+    metadata_rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    download_rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    cors: Option<Cors>,
+    #[serde(default)]
+    write_allowed_networks: Option<Vec<IpNet>>,
+    #[serde(default)]
+    trusted_proxies: Vec<IpNet>,
+    #[serde(default)]
+    jwt: Option<Jwt>,
+}
+
+/// Accepts JWTs, such as the ones issued to CI jobs, as bearer tokens if they
+/// are signed by a key in `jwks_path` and were issued by `issuer` for `audience`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Jwt {
+    jwks_path: PathBuf,
+    issuer: String,
+    audience: String,
+    #[serde(default)]
+    rules: Vec<JwtRule>
+}
+
+impl Jwt {
+    /// JSON Web Key Set holding the keys JWTs may be signed with.
+    pub fn jwks_path(&self) -> &Path {
+        &self.jwks_path
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    pub fn rules(&self) -> &Vec<JwtRule> {
+        &self.rules
+    }
+
+    fn is_valid(&self) -> bool {
+        self.rules.iter().all(|rule| rule.scopes.iter().all(|scope| scope.parse::<Grant>().is_ok()))
+    }
+}
+
+/// Grants `scopes`, written like the scopes of the `token create` command, to
+/// JWTs whose claims have all of the given values.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JwtRule {
+    claims: HashMap<String, String>,
+    scopes: Vec<String>
+}
+
+impl JwtRule {
+    pub fn matches(&self, claims: &serde_json::Map<String, serde_json::Value>) -> bool {
+        self.claims.iter().all(|(claim, value)| {
+            claims.get(claim).and_then(|c| c.as_str()).is_some_and(|c| c == value)
+        })
+    }
+
+    /// The rule's scopes. They are checked when the config is opened, so none are left out.
+    pub fn grants(&self) -> Vec<Grant> {
+        self.scopes.iter().filter_map(|scope| scope.parse().ok()).collect()
+    }
+}
+
+/// Cross-origin access for browser clients. An origin of `*` allows any origin.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Cors {
+    allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_allowed_methods")]
+    allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_allowed_headers")]
+    allowed_headers: Vec<String>,
+    #[serde(default)]
+    exposed_headers: Vec<String>,
+    #[serde(default = "default_cors_max_age")]
+    max_age: u64
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+fn default_cors_allowed_headers() -> Vec<String> {
+    vec!["authorization".to_string(), "content-type".to_string()]
+}
+
+fn default_cors_max_age() -> u64 {
+    60 * 60
+}
+
+impl Cors {
+    pub fn allowed_origins(&self) -> &Vec<String> {
+        &self.allowed_origins
+    }
+
+    pub fn allowed_methods(&self) -> &Vec<String> {
+        &self.allowed_methods
+    }
+
+    pub fn allowed_headers(&self) -> &Vec<String> {
+        &self.allowed_headers
+    }
+
+    /// Response headers, besides the always safe ones, that scripts may read.
+    pub fn exposed_headers(&self) -> &Vec<String> {
+        &self.exposed_headers
+    }
+
+    /// Seconds browsers may cache the result of a preflight request.
+    pub fn max_age(&self) -> u64 {
+        self.max_age
+    }
+
+    fn is_valid(&self) -> bool {
+        self.allowed_origins.iter().all(|o| o == "*" || HeaderValue::from_str(o).is_ok())
+            && self.allowed_methods.iter().all(|m| Method::from_bytes(m.as_bytes()).is_ok())
+            && self.allowed_headers.iter().chain(&self.exposed_headers).all(|h| HeaderName::from_bytes(h.as_bytes()).is_ok())
+    }
+}
+
+/// Slowest refill rate allowed, one request a day. Slower rates would make
+/// clients wait longer than a `Duration` can hold.
+const MIN_PER_SECOND: f64 = 1.0 / 86400.0;
+
+/// Limits each client to `burst` requests at once, refilled at `per_second`.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct RateLimit {
+    burst: u32,
+    per_second: f64
+}
+
+impl RateLimit {
+    pub fn burst(&self) -> f64 {
+        self.burst as f64
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+
+    fn is_valid(&self) -> bool {
+        self.burst >= 1 && self.per_second >= MIN_PER_SECOND
+    }
+}
+
+fn default_max_upload_size() -> usize {
+    512 * 1024 * 1024
+}
+
+fn default_seed_db() -> bool {
+    true
+}
+
+fn default_upload_session_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_max_download_url_ttl() -> u64 {
+    7 * 24 * 60 * 60
 }
 
 impl Default for Config {
@@ -16,32 +194,51 @@ impl Default for Config {
             bind_addr: ([0, 0, 0, 0], 3000).into(),
             db_path: shellexpand::full("./releases.db3").unwrap().to_string().into(),
             data_dir: shellexpand::full("./data").unwrap().to_string().into(),
-            log_level: "WARN".to_string()
+            log_level: "WARN".to_string(),
+            max_upload_size: default_max_upload_size(),
+            seed_db: default_seed_db(),
+            upload_session_ttl: default_upload_session_ttl(),
+            download_secret: None,
+            max_download_url_ttl: default_max_download_url_ttl(),
+            metadata_rate_limit: None,
+            download_rate_limit: None,
+            cors: None,
+            write_allowed_networks: None,
+            trusted_proxies: Vec::new(),
+            jwt: None
         }
     }
 }
 
 impl Config {
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Config, ()> {
+    /// Reads the config file at `path`, or `None` if there is none. A file that
+    /// exists but can't be read or is invalid is an error rather than being
+    /// skipped, so the server never runs without the restrictions it configures.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Option<Config>, Box<dyn Error>> {
         let mut file = match File::open(path) {
             Ok(file) => file,
-            Err(_) => return Err(())
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Box::new(e))
         };
         let mut file_contents = String::new();
-        if file.read_to_string(&mut file_contents).is_err() {
-            return Err(())
-        }
+        file.read_to_string(&mut file_contents)?;
 
-        let toml: Config = match toml::from_str(&file_contents) {
-            Ok(toml) => toml,
-            Err(_) => return Err(())
-        };
+        let toml: Config = toml::from_str(&file_contents)?;
 
         if log::LevelFilter::from_str(&toml.log_level).is_err() {
-            return Err(())
-        } else {
-            return Ok(toml)
+            return Err(format!("invalid log_level \"{}\"", toml.log_level).into())
+        }
+        if [toml.metadata_rate_limit, toml.download_rate_limit].iter().flatten().any(|limit| !limit.is_valid()) {
+            return Err("invalid rate limit, burst must be at least 1 and per_second at least one per day".into())
+        }
+        if toml.cors.as_ref().is_some_and(|cors| !cors.is_valid()) {
+            return Err("invalid cors origin, method or header".into())
         }
+        if toml.jwt.as_ref().is_some_and(|jwt| !jwt.is_valid()) {
+            return Err("invalid jwt rule scope".into())
+        }
+
+        Ok(Some(toml))
     }
 
     pub fn bind_addr(&self) -> &SocketAddr {
@@ -56,6 +253,60 @@ impl Config {
         &self.data_dir
     }
 
+    pub fn max_upload_size(&self) -> usize {
+        self.max_upload_size
+    }
+
+    /// Whether the default repositories and channels should be created on startup.
+    pub fn seed_db(&self) -> bool {
+        self.seed_db
+    }
+
+    /// Seconds after its last chunk that an unfinished upload session expires.
+    pub fn upload_session_ttl(&self) -> u64 {
+        self.upload_session_ttl
+    }
+
+    /// Secret used to sign download URLs. Signed URLs can't be minted without one.
+    pub fn download_secret(&self) -> Option<&str> {
+        self.download_secret.as_deref()
+    }
+
+    /// Maximum number of seconds a signed download URL may be valid for.
+    pub fn max_download_url_ttl(&self) -> u64 {
+        self.max_download_url_ttl
+    }
+
+    /// Per-client limit on all routes except downloads. Unlimited if `None`.
+    pub fn metadata_rate_limit(&self) -> Option<RateLimit> {
+        self.metadata_rate_limit
+    }
+
+    /// Per-client limit on the download routes. Unlimited if `None`.
+    pub fn download_rate_limit(&self) -> Option<RateLimit> {
+        self.download_rate_limit
+    }
+
+    /// CORS settings. No CORS headers are sent if `None`.
+    pub fn cors(&self) -> Option<&Cors> {
+        self.cors.as_ref()
+    }
+
+    /// Networks the admin and mutating routes answer to. Any network if `None`.
+    pub fn write_allowed_networks(&self) -> Option<&Vec<IpNet>> {
+        self.write_allowed_networks.as_ref()
+    }
+
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted to name the client.
+    pub fn trusted_proxies(&self) -> &Vec<IpNet> {
+        &self.trusted_proxies
+    }
+
+    /// JWT authentication settings. Only stored tokens are accepted if `None`.
+    pub fn jwt(&self) -> Option<&Jwt> {
+        self.jwt.as_ref()
+    }
+
     pub fn log_level(&self) -> log::LevelFilter {
         log::LevelFilter::from_str(&self.log_level).unwrap()
     }
@@ -1,15 +1,24 @@
-use std::{error::Error, fmt::Display, path::Path};
+use std::{error::Error, fmt::Display, path::Path, time::{SystemTime, UNIX_EPOCH}};
 
 use log::warn;
 use rusqlite::{params, Connection};
+use serde::Serialize;
 
-use crate::release::{Artifact, ArtifactType, Release, Channel, Repository};
+use crate::{audit::{AuditEntry, AuditFilter}, auth::{self, Action, Grant, Token}, release::{Artifact, ArtifactRevision, ArtifactType, Hashes, Release, ReleaseState, Channel, Repository, UploadSession}};
 
 type Result<T> = core::result::Result<T, Box<dyn Error>>;
 
+/// First segments of routes that aren't `/{repository}/...`, so can't be repository keys.
+const RESERVED_REPOSITORY_KEYS: [&str; 1] = ["admin"];
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum DbError {
     NoSuchKey,
+    KeyExists,
+    InvalidKey,
+    NotEmpty,
+    Yanked,
+    Unpublished,
     ParseErr
 }
 
@@ -17,6 +26,11 @@ impl Display for DbError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DbError::NoSuchKey => write!(f, "No such key"),
+            DbError::KeyExists => write!(f, "Key already exists"),
+            DbError::InvalidKey => write!(f, "Invalid key"),
+            DbError::NotEmpty => write!(f, "Key is still in use"),
+            DbError::Yanked => write!(f, "Release is yanked"),
+            DbError::Unpublished => write!(f, "Release is not published"),
             DbError::ParseErr => write!(f, "Parse error")
         }
     }
@@ -24,8 +38,18 @@ impl Display for DbError {
 
 impl Error for DbError { }
 
+/// An artifact whose file has already been written to the data directory.
+pub struct StoredArtifact {
+    pub name: String,
+    pub path: String,
+    pub artifact_type: ArtifactType,
+    pub size: u64,
+    pub hashes: Hashes
+}
+
 pub struct ReleaseDatabase {
-    connection: Connection
+    connection: Connection,
+    actor: Option<(Option<u32>, String)>
 }
 
 impl ReleaseDatabase {
@@ -33,10 +57,18 @@ impl ReleaseDatabase {
         let con = Connection::open(path)?;
         Self::init_db(&con)?;
         Ok(Self {
-            connection: con
+            connection: con,
+            actor: None
         })
     }
 
+    /// Attributes changes made through this database to the given token in
+    /// the audit log. Changes without an actor were made on the host itself.
+    pub fn with_actor(mut self, token: &Token) -> Self {
+        self.actor = Some((token.id(), token.name().to_string()));
+        self
+    }
+
     fn init_db(connection: &Connection) -> Result<()> {
         connection.execute(
             "CREATE TABLE IF NOT EXISTS repository (
@@ -44,13 +76,6 @@ impl ReleaseDatabase {
             )",
             ()
         )?;
-        connection.execute(
-            "INSERT OR IGNORE INTO repository(id)
-            VALUES
-                (\"mod\"),
-                (\"updater\")",
-            ()
-        )?;
         connection.execute(
             "CREATE TABLE IF NOT EXISTS channel (
                 id          TEXT NOT NULL,
@@ -60,54 +85,1187 @@ impl ReleaseDatabase {
             )",
             ()
         )?;
-        connection.execute(
-            "INSERT OR IGNORE INTO channel(id, repository)
-            VALUES
-                (\"stable\", \"mod\"),
-                (\"snapshot\", \"mod\"),
-                (\"nightly\", \"mod\"),
-                (\"release\", \"updater\")",
-                ()
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS release (
+                id          TEXT NOT NULL,
+                repository  TEXT NOT NULL,
+                channel     TEXT NOT NULL,
+                name        TEXT NOT NULL,
+                created_at  INTEGER NOT NULL,
+                CONSTRAINT key PRIMARY KEY (id, repository, channel),
+                FOREIGN KEY(repository) REFERENCES repository(id),
+                FOREIGN KEY(channel) REFERENCES channel(id)
+            )",
+            ()
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS artifact (
+                id          INTEGER NOT NULL,
+                repository  TEXT NOT NULL,
+                channel     TEXT NOT NULL,
+                release     INTEGER NOT NULL,
+                name        TEXT NOT NULL,
+                path        TEXT NOT NULL,
+                type        INTEGER NOT NULL,
+                CONSTRAINT key PRIMARY KEY (id, repository, channel, release),
+                FOREIGN KEY(repository) REFERENCES repository(id),
+                FOREIGN KEY(channel) REFERENCES channel(id),
+                FOREIGN KEY(release) REFERENCES release(id)
+            )",
+            ()
+        )?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS token (
+                id          INTEGER PRIMARY KEY,
+                name        TEXT NOT NULL,
+                hash        TEXT NOT NULL UNIQUE,
+                created_at  INTEGER NOT NULL
+            )",
+            ()
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS token_grant (
+                token       INTEGER NOT NULL,
+                repository  TEXT NOT NULL,
+                channel     TEXT NOT NULL,
+                action      INTEGER NOT NULL,
+                CONSTRAINT key PRIMARY KEY (token, repository, channel, action),
+                FOREIGN KEY(token) REFERENCES token(id)
+            )",
+            ()
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS artifact_revision (
+                revision    INTEGER NOT NULL,
+                repository  TEXT NOT NULL,
+                channel     TEXT NOT NULL,
+                release     TEXT NOT NULL,
+                artifact    INTEGER NOT NULL,
+                name        TEXT NOT NULL,
+                path        TEXT NOT NULL,
+                type        INTEGER NOT NULL,
+                replaced_at INTEGER NOT NULL,
+                CONSTRAINT key PRIMARY KEY (revision, repository, channel, release, artifact),
+                FOREIGN KEY(artifact) REFERENCES artifact(id)
+            )",
+            ()
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS upload_session (
+                id          TEXT PRIMARY KEY,
+                repository  TEXT NOT NULL,
+                channel     TEXT NOT NULL,
+                release     TEXT NOT NULL,
+                name        TEXT NOT NULL,
+                type        INTEGER NOT NULL,
+                size        INTEGER,
+                sha256      TEXT NOT NULL,
+                expires_at  INTEGER NOT NULL
+            )",
+            ()
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS upload_chunk (
+                session     TEXT NOT NULL,
+                id          INTEGER NOT NULL,
+                size        INTEGER NOT NULL,
+                sha256      TEXT NOT NULL,
+                CONSTRAINT key PRIMARY KEY (session, id),
+                FOREIGN KEY(session) REFERENCES upload_session(id)
+            )",
+            ()
+        )?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id          INTEGER PRIMARY KEY,
+                token       INTEGER,
+                action      TEXT NOT NULL,
+                target      TEXT NOT NULL,
+                created_at  INTEGER NOT NULL,
+                before_json TEXT,
+                after_json  TEXT
+            )",
+            ()
+        )?;
+
+        migrate_column(connection, "release", "yanked", "INTEGER NOT NULL DEFAULT 0")?;
+        migrate_column(connection, "release", "yank_reason", "TEXT")?;
+        migrate_column(connection, "release", "promoted_from", "TEXT")?;
+        migrate_column(connection, "release", "state", "INTEGER NOT NULL DEFAULT 1")?;
+        migrate_column(connection, "token", "revoked_at", "INTEGER")?;
+        migrate_column(connection, "channel", "private", "INTEGER NOT NULL DEFAULT 0")?;
+        migrate_column(connection, "audit_log", "actor", "TEXT")?;
+        migrate_column(connection, "artifact", "sha256", "TEXT")?;
+        migrate_column(connection, "artifact_revision", "sha256", "TEXT")?;
+        migrate_column(connection, "artifact", "content_type", "TEXT")?;
+        migrate_column(connection, "artifact_revision", "content_type", "TEXT")?;
+        for table in ["artifact", "artifact_revision"] {
+            migrate_column(connection, table, "size", "INTEGER")?;
+            migrate_column(connection, table, "sha1", "TEXT")?;
+            migrate_column(connection, table, "sha512", "TEXT")?;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the default repositories and channels if they don't exist yet.
+    pub fn seed_db(&self) -> Result<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let inserted = transaction.execute(
+            "INSERT OR IGNORE INTO repository(id)
+            VALUES
+                (\"mod\"),
+                (\"updater\")",
+            ()
+        )? + transaction.execute(
+            "INSERT OR IGNORE INTO channel(id, repository)
+            VALUES
+                (\"stable\", \"mod\"),
+                (\"snapshot\", \"mod\"),
+                (\"nightly\", \"mod\"),
+                (\"release\", \"updater\")",
+                ()
+        )?;
+        if inserted > 0 {
+            self.audit("seed_db", "/".to_string(), None, None)?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn create_repository<S: Into<String>>(&self, repository_id: S) -> Result<Repository> {
+        let repository_id: String = repository_id.into();
+
+        if !is_valid_repository_key(&repository_id) {
+            return Err(Box::new(DbError::InvalidKey));
+        }
+        if DbRepository::read(self, &repository_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let db_repository = DbRepository {
+            id: repository_id.clone()
+        };
+        db_repository.insert(self)?;
+        let repository = self.read_repository(&repository_id, |_| true, |_| true)?;
+        self.audit("create_repository", format!("/{repository_id}"), None, to_json(&repository))?;
+        transaction.commit()?;
+
+        Ok(repository)
+    }
+
+    pub fn rename_repository<S: Into<String>>(&self, repository_id: S, new_repository_id: S) -> Result<Repository> {
+        let repository_id: String = repository_id.into();
+        let new_repository_id: String = new_repository_id.into();
+
+        if !is_valid_repository_key(&new_repository_id) {
+            return Err(Box::new(DbError::InvalidKey));
+        }
+        let before = match self.read_repository(&repository_id, |_| true, |_| true) {
+            Ok(repository) => repository,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+        if DbRepository::read(self, &new_repository_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        for table in ["repository", "channel", "release", "artifact", "artifact_revision", "upload_session", "token_grant"] {
+            let column = if table == "repository" { "id" } else { "repository" };
+            transaction.execute(
+                &format!("UPDATE {table} SET {column}=?2 WHERE {column}=?1"),
+                params![repository_id, new_repository_id]
+            ).map_err(key_exists_on_conflict)?;
+        }
+        let repository = self.read_repository(&new_repository_id, |_| true, |_| true)?;
+        self.audit("rename_repository", format!("/{repository_id}"), to_json(&before), to_json(&repository))?;
+        transaction.commit()?;
+
+        Ok(repository)
+    }
+
+    pub fn delete_repository<S: Into<String>>(&self, repository_id: S) -> Result<()> {
+        let repository_id: String = repository_id.into();
+
+        let before = match self.read_repository(&repository_id, |_| true, |_| true) {
+            Ok(repository) => repository,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let channel_count: u32 = self.connection.query_row(
+            "SELECT COUNT(*) FROM channel WHERE repository=?1",
+            params![repository_id],
+            |row| row.get(0)
+        )?;
+        if channel_count > 0 {
+            return Err(Box::new(DbError::NotEmpty));
+        }
+
+        // Grants must not carry over to a repository later created with the same id.
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "DELETE FROM token_grant WHERE repository=?1",
+            params![repository_id]
+        )?;
+        transaction.execute(
+            "DELETE FROM repository WHERE id=?1",
+            params![repository_id]
+        )?;
+        self.audit("delete_repository", format!("/{repository_id}"), to_json(&before), None)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn create_channel<S: Into<String>>(&self, repository_id: S, channel_id: S, private: bool) -> Result<Channel> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+
+        if !is_valid_key(&channel_id) {
+            return Err(Box::new(DbError::InvalidKey));
+        }
+        if DbRepository::read(self, &repository_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+        if DbChannel::read(self, &repository_id, &channel_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let db_channel = DbChannel {
+            id: channel_id.clone(),
+            private
+        };
+        db_channel.insert(self, &repository_id)?;
+        let channel = self.read_channel(&repository_id, &channel_id, true, true)?;
+        self.audit("create_channel", format!("/{repository_id}/{channel_id}"), None, to_json(&channel))?;
+        transaction.commit()?;
+
+        Ok(channel)
+    }
+
+    /// Renames a channel and changes whether it is private. Fields that are
+    /// `None` are left unchanged.
+    pub fn update_channel<S: Into<String>>(&self, repository_id: S, channel_id: S, new_channel_id: Option<String>, private: Option<bool>) -> Result<Channel> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let new_channel_id = new_channel_id.unwrap_or_else(|| channel_id.clone());
+
+        if !is_valid_key(&new_channel_id) {
+            return Err(Box::new(DbError::InvalidKey));
+        }
+        let before = match self.read_channel(&repository_id, &channel_id, true, true) {
+            Ok(channel) => channel,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+        if new_channel_id != channel_id && DbChannel::read(self, &repository_id, &new_channel_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE channel SET private=COALESCE(?3, private) WHERE repository=?1 AND id=?2",
+            params![repository_id, channel_id, private]
+        )?;
+        for table in ["channel", "release", "artifact", "artifact_revision", "upload_session", "token_grant"] {
+            let column = if table == "channel" { "id" } else { "channel" };
+            transaction.execute(
+                &format!("UPDATE {table} SET {column}=?3 WHERE repository=?1 AND {column}=?2"),
+                params![repository_id, channel_id, new_channel_id]
+            ).map_err(key_exists_on_conflict)?;
+        }
+        transaction.execute(
+            "UPDATE release SET promoted_from=?3 WHERE repository=?1 AND promoted_from=?2",
+            params![repository_id, channel_id, new_channel_id]
+        )?;
+        let channel = self.read_channel(&repository_id, &new_channel_id, true, true)?;
+        self.audit("update_channel", format!("/{repository_id}/{channel_id}"), to_json(&before), to_json(&channel))?;
+        transaction.commit()?;
+
+        Ok(channel)
+    }
+
+    pub fn delete_channel<S: Into<String>>(&self, repository_id: S, channel_id: S) -> Result<()> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+
+        let before = match self.read_channel(&repository_id, &channel_id, true, true) {
+            Ok(channel) => channel,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let release_count: u32 = self.connection.query_row(
+            "SELECT COUNT(*) FROM release WHERE repository=?1 AND channel=?2",
+            params![repository_id, channel_id],
+            |row| row.get(0)
+        )?;
+        if release_count > 0 {
+            return Err(Box::new(DbError::NotEmpty));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "DELETE FROM token_grant WHERE repository=?1 AND channel=?2",
+            params![repository_id, channel_id]
+        )?;
+        transaction.execute(
+            "DELETE FROM channel WHERE repository=?1 AND id=?2",
+            params![repository_id, channel_id]
+        )?;
+        self.audit("delete_channel", format!("/{repository_id}/{channel_id}"), to_json(&before), None)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn create_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, name: S, created_at: Option<u64>) -> Result<Release> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        if !is_valid_key(&release_id) {
+            return Err(Box::new(DbError::InvalidKey));
+        }
+        if DbChannel::read(self, &repository_id, &channel_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+        if DbRelease::read(self, &repository_id, &channel_id, &release_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let db_release = DbRelease {
+            id: release_id.clone(),
+            name: name.into(),
+            created_at: created_at.unwrap_or_else(unix_time),
+            yanked: false,
+            yank_reason: None,
+            promoted_from: None,
+            state: ReleaseState::Draft.into()
+        };
+        db_release.insert(self, &repository_id, &channel_id)?;
+        let release = self.read_release(&repository_id, &channel_id, &release_id, true, true)?;
+        self.audit("create_release", format!("/{repository_id}/{channel_id}/{release_id}"), None, to_json(&release))?;
+        transaction.commit()?;
+
+        Ok(release)
+    }
+
+    /// Creates an already published release together with all of its artifacts
+    /// in a single transaction. Nothing is inserted if any step fails.
+    pub fn create_release_bundle<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, name: S, created_at: Option<u64>, artifacts: Vec<StoredArtifact>) -> Result<Release> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        if !is_valid_key(&release_id) {
+            return Err(Box::new(DbError::InvalidKey));
+        }
+        if DbChannel::read(self, &repository_id, &channel_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+        if DbRelease::read(self, &repository_id, &channel_id, &release_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let db_release = DbRelease {
+            id: release_id.clone(),
+            name: name.into(),
+            created_at: created_at.unwrap_or_else(unix_time),
+            yanked: false,
+            yank_reason: None,
+            promoted_from: None,
+            state: ReleaseState::Published.into()
+        };
+        db_release.insert(self, &repository_id, &channel_id)?;
+
+        for (id, artifact) in artifacts.into_iter().enumerate() {
+            let db_artifact = DbArtifact {
+                id: id as u32,
+                name: artifact.name,
+                path: artifact.path,
+                artifact_type: artifact.artifact_type.into(),
+                size: Some(artifact.size),
+                sha1: artifact.hashes.sha1().map(str::to_string),
+                sha256: artifact.hashes.sha256().map(str::to_string),
+                sha512: artifact.hashes.sha512().map(str::to_string),
+                content_type: None
+            };
+            db_artifact.insert(self, &repository_id, &channel_id, &release_id)?;
+        }
+        let release = self.read_release(&repository_id, &channel_id, &release_id, true, true)?;
+        self.audit("create_release_bundle", format!("/{repository_id}/{channel_id}/{release_id}"), None, to_json(&release))?;
+        transaction.commit()?;
+
+        Ok(release)
+    }
+
+    /// Updates the metadata of a release. Fields that are `None` are left unchanged.
+    pub fn update_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, name: Option<String>, created_at: Option<u64>) -> Result<Release> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        let before = match self.read_release(&repository_id, &channel_id, &release_id, true, true) {
+            Ok(release) => release,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE release SET
+                name=COALESCE(?4, name),
+                created_at=COALESCE(?5, created_at)
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id, name, created_at]
+        )?;
+        let release = self.read_release(&repository_id, &channel_id, &release_id, true, true)?;
+        self.audit("update_release", format!("/{repository_id}/{channel_id}/{release_id}"), to_json(&before), to_json(&release))?;
+        transaction.commit()?;
+
+        Ok(release)
+    }
+
+    /// Publishes a draft release, making it visible on the public routes.
+    pub fn publish_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S) -> Result<Release> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        let before = match self.read_release(&repository_id, &channel_id, &release_id, true, true) {
+            Ok(release) => release,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE release SET state=?4
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id, u32::from(ReleaseState::Published)]
+        )?;
+        let release = self.read_release(&repository_id, &channel_id, &release_id, true, true)?;
+        self.audit("publish_release", format!("/{repository_id}/{channel_id}/{release_id}"), to_json(&before), to_json(&release))?;
+        transaction.commit()?;
+
+        Ok(release)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_artifact<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, name: S, path: S, artifact_type: ArtifactType, size: u64, hashes: Hashes) -> Result<Artifact> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        if DbRelease::read(self, &repository_id, &channel_id, &release_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let artifact = self.insert_artifact(&repository_id, &channel_id, &release_id, name.into(), path.into(), artifact_type, size, &hashes)?;
+        transaction.commit()?;
+
+        Ok(artifact)
+    }
+
+    /// Inserts an artifact with the next free id. Has to be called in a transaction.
+    #[allow(clippy::too_many_arguments)]
+    fn insert_artifact(&self, repository_id: &str, channel_id: &str, release_id: &str, name: String, path: String, artifact_type: ArtifactType, size: u64, hashes: &Hashes) -> Result<Artifact> {
+        let db_artifact = DbArtifact {
+            id: DbArtifact::next_id(self, repository_id, channel_id, release_id)?,
+            name,
+            path,
+            artifact_type: artifact_type.into(),
+            size: Some(size),
+            sha1: hashes.sha1().map(str::to_string),
+            sha256: hashes.sha256().map(str::to_string),
+            sha512: hashes.sha512().map(str::to_string),
+            content_type: None
+        };
+        db_artifact.insert(self, repository_id, channel_id, release_id)?;
+        let artifact = self.read_artifact(repository_id, channel_id, release_id, db_artifact.id, true, true)?;
+        self.audit("create_artifact", format!("/{repository_id}/{channel_id}/{release_id}/{}", db_artifact.id), None, to_json(&artifact))?;
+
+        Ok(artifact)
+    }
+
+    /// Marks a release as yanked. Yanked releases stay readable by id but are
+    /// no longer listed in their channel.
+    pub fn yank_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, reason: Option<String>) -> Result<Release> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        let before = match self.read_release(&repository_id, &channel_id, &release_id, true, true) {
+            Ok(release) => release,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE release SET yanked=1, yank_reason=?4
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id, reason]
+        )?;
+        let release = self.read_release(&repository_id, &channel_id, &release_id, true, true)?;
+        self.audit("yank_release", format!("/{repository_id}/{channel_id}/{release_id}"), to_json(&before), to_json(&release))?;
+        transaction.commit()?;
+
+        Ok(release)
+    }
+
+    /// Copies a release and its artifacts into another channel of the same
+    /// repository. The copied artifacts share their files with the original.
+    pub fn promote_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, target_channel_id: S) -> Result<Release> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+        let target_channel_id: String = target_channel_id.into();
+
+        let db_release = match DbRelease::read(self, &repository_id, &channel_id, &release_id) {
+            Ok(db_release) => db_release,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+        if db_release.yanked {
+            return Err(Box::new(DbError::Yanked));
+        }
+        if db_release.state != u32::from(ReleaseState::Published) {
+            return Err(Box::new(DbError::Unpublished));
+        }
+        if DbChannel::read(self, &repository_id, &target_channel_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+        if DbRelease::read(self, &repository_id, &target_channel_id, &release_id).is_ok() {
+            return Err(Box::new(DbError::KeyExists));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT INTO release(id, repository, channel, name, created_at, promoted_from, state)
+            SELECT id, repository, ?4, name, created_at, channel, state
+            FROM release
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id, target_channel_id]
+        ).map_err(key_exists_on_conflict)?;
+        transaction.execute(
+            "INSERT INTO artifact(id, repository, channel, release, name, path, type, size, sha1, sha256, sha512, content_type)
+            SELECT id, repository, ?4, release, name, path, type, size, sha1, sha256, sha512, content_type
+            FROM artifact
+            WHERE
+                release=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id, target_channel_id]
+        )?;
+        transaction.execute(
+            "INSERT INTO artifact_revision(revision, repository, channel, release, artifact, name, path, type, replaced_at, size, sha1, sha256, sha512, content_type)
+            SELECT revision, repository, ?4, release, artifact, name, path, type, replaced_at, size, sha1, sha256, sha512, content_type
+            FROM artifact_revision
+            WHERE
+                release=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id, target_channel_id]
+        )?;
+        let release = self.read_release(&repository_id, &target_channel_id, &release_id, true, true)?;
+        self.audit("promote_release", format!("/{repository_id}/{target_channel_id}/{release_id}"), None, to_json(&release))?;
+        transaction.commit()?;
+
+        Ok(release)
+    }
+
+    /// Deletes a release and its artifacts. Returns the paths of the files that
+    /// are no longer referenced by any artifact and can be removed from disk.
+    pub fn delete_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S) -> Result<Vec<String>> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        let before = match self.read_release(&repository_id, &channel_id, &release_id, true, true) {
+            Ok(release) => release,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let mut paths = Vec::new();
+        for table in ["artifact", "artifact_revision"] {
+            let mut path_statement = transaction.prepare(&format!(
+                "SELECT path FROM {table}
+                WHERE
+                    repository=?1 AND
+                    channel=?2 AND
+                    release=?3"
+            ))?;
+            let table_paths: std::result::Result<Vec<_>, _> = path_statement
+                .query_map(params![repository_id, channel_id, release_id], |row| row.get::<usize, String>(0))?
+                .collect();
+            paths.extend(table_paths?);
+
+            transaction.execute(
+                &format!("DELETE FROM {table}
+                WHERE
+                    repository=?1 AND
+                    channel=?2 AND
+                    release=?3"),
+                params![repository_id, channel_id, release_id]
+            )?;
+        }
+        transaction.execute(
+            "DELETE FROM release
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3",
+            params![release_id, repository_id, channel_id]
+        )?;
+
+        let mut unused_paths = Vec::new();
+        for path in paths {
+            let references: u32 = transaction.query_row(
+                "SELECT
+                    (SELECT COUNT(*) FROM artifact WHERE path=?1) +
+                    (SELECT COUNT(*) FROM artifact_revision WHERE path=?1)",
+                params![path],
+                |row| row.get(0)
+            )?;
+            if references == 0 && !unused_paths.contains(&path) {
+                unused_paths.push(path);
+            }
+        }
+        self.audit("delete_release", format!("/{repository_id}/{channel_id}/{release_id}"), to_json(&before), None)?;
+        transaction.commit()?;
+
+        Ok(unused_paths)
+    }
+
+    /// Updates the metadata of an artifact. Fields that are `None` are left
+    /// unchanged, and an empty content type removes the override.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_artifact<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, artifact_id: u32, name: Option<String>, artifact_type: Option<ArtifactType>, content_type: Option<String>) -> Result<Artifact> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        let before = match self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, true, true) {
+            Ok(artifact) => artifact,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE artifact SET
+                name=COALESCE(?5, name),
+                type=COALESCE(?6, type),
+                content_type=CASE WHEN ?7 IS NULL THEN content_type ELSE NULLIF(?7, '') END
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3 AND
+                release=?4",
+            params![artifact_id, repository_id, channel_id, release_id, name, artifact_type.map(u32::from), content_type]
+        )?;
+        let artifact = self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, true, true)?;
+        self.audit("update_artifact", format!("/{repository_id}/{channel_id}/{release_id}/{artifact_id}"), to_json(&before), to_json(&artifact))?;
+        transaction.commit()?;
+
+        Ok(artifact)
+    }
+
+    /// Replaces the file of an artifact, keeping the previous file and metadata
+    /// as a revision. `name` and `artifact_type` are left unchanged if `None`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn replace_artifact_file<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, artifact_id: u32, path: S, size: u64, hashes: Hashes, name: Option<String>, artifact_type: Option<ArtifactType>) -> Result<Artifact> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        let db_artifact = match DbArtifact::read(self, &repository_id, &channel_id, &release_id, artifact_id) {
+            Ok(db_artifact) => db_artifact,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+        let before = self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, true, true)?;
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let db_revision = DbArtifactRevision {
+            revision: DbArtifactRevision::next_id(self, &repository_id, &channel_id, &release_id, artifact_id)?,
+            name: db_artifact.name,
+            path: db_artifact.path,
+            artifact_type: db_artifact.artifact_type,
+            replaced_at: unix_time(),
+            size: db_artifact.size,
+            sha1: db_artifact.sha1,
+            sha256: db_artifact.sha256,
+            sha512: db_artifact.sha512,
+            content_type: db_artifact.content_type
+        };
+        db_revision.insert(self, &repository_id, &channel_id, &release_id, artifact_id)?;
+        transaction.execute(
+            "UPDATE artifact SET
+                path=?5,
+                size=?6,
+                sha1=?7,
+                sha256=?8,
+                sha512=?9,
+                name=COALESCE(?10, name),
+                type=COALESCE(?11, type)
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3 AND
+                release=?4",
+            params![artifact_id, repository_id, channel_id, release_id, path.into(), size, hashes.sha1(), hashes.sha256(), hashes.sha512(), name, artifact_type.map(u32::from)]
+        )?;
+        let artifact = self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, true, true)?;
+        self.audit("replace_artifact_file", format!("/{repository_id}/{channel_id}/{release_id}/{artifact_id}"), to_json(&before), to_json(&artifact))?;
+        transaction.commit()?;
+
+        Ok(artifact)
+    }
+
+    /// Reads the earlier revisions of an artifact, oldest first.
+    pub fn read_artifact_revisions<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, artifact_id: u32, include_private: bool, include_drafts: bool) -> Result<Vec<ArtifactRevision>> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, include_private, include_drafts)?;
+
+        let mut revision_statement = self.connection.prepare(
+            "SELECT revision FROM artifact_revision
+            WHERE
+                repository=?1 AND
+                channel=?2 AND
+                release=?3 AND
+                artifact=?4
+            ORDER BY revision"
+        )?;
+        let revision_ids: std::result::Result<Vec<_>, _> = revision_statement
+            .query_map(params![repository_id, channel_id, release_id, artifact_id], |row| row.get::<usize, u32>(0))?
+            .collect();
+        let revision_ids = match revision_ids {
+            Ok(revision_ids) => revision_ids,
+            Err(_) => {
+                warn!(target: "read_db", "artifact with id {artifact_id} has revision with invalid id");
+                return Err(Box::new(DbError::ParseErr));
+            }
+        };
+
+        revision_ids
+            .into_iter()
+            .map(|id| self.read_artifact_revision(&repository_id, &channel_id, &release_id, artifact_id, id, include_private, include_drafts))
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_artifact_revision<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, artifact_id: u32, revision: u32, include_private: bool, include_drafts: bool) -> Result<ArtifactRevision> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+
+        self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, include_private, include_drafts)?;
+
+        let db_revision = match DbArtifactRevision::read(self, &repository_id, &channel_id, &release_id, artifact_id, revision) {
+            Ok(db_revision) => db_revision,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        match db_revision.try_into_artifact_revision() {
+            Ok(revision) => Ok(revision),
+            Err(_) => Err(Box::new(DbError::ParseErr))
+        }
+    }
+
+    /// Starts a chunked upload of an artifact for the given release. The
+    /// session expires `ttl` seconds after its last chunk was received.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_upload_session<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, name: S, artifact_type: ArtifactType, size: Option<u64>, sha256: S, ttl: u64) -> Result<UploadSession> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+        let sha256: String = sha256.into().to_ascii_lowercase();
+
+        if DbRelease::read(self, &repository_id, &channel_id, &release_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+
+        let db_session = DbUploadSession {
+            id: hex::encode(rand::random::<[u8; 16]>()),
+            name: name.into(),
+            artifact_type: artifact_type.into(),
+            size,
+            sha256,
+            expires_at: unix_time() + ttl
+        };
+        let transaction = self.connection.unchecked_transaction()?;
+        db_session.insert(self, &repository_id, &channel_id, &release_id)?;
+        let session = self.read_upload_session(&repository_id, &channel_id, &release_id, &db_session.id)?;
+        self.audit("create_upload_session", format!("/{repository_id}/{channel_id}/{release_id}/uploads/{}", db_session.id), None, to_json(&session))?;
+        transaction.commit()?;
+
+        Ok(session)
+    }
+
+    pub fn read_upload_session<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, session_id: S) -> Result<UploadSession> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+        let session_id: String = session_id.into();
+
+        let db_session = match DbUploadSession::read(self, &repository_id, &channel_id, &release_id, &session_id) {
+            Ok(db_session) if db_session.expires_at >= unix_time() => db_session,
+            _ => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let mut chunk_statement = self.connection.prepare(
+            "SELECT id FROM upload_chunk WHERE session=?1 ORDER BY id"
+        )?;
+        let chunk_ids: std::result::Result<Vec<_>, _> = chunk_statement
+            .query_map(params![session_id], |row| row.get::<usize, u32>(0))?
+            .collect();
+        let chunk_ids = match chunk_ids {
+            Ok(chunk_ids) => chunk_ids,
+            Err(_) => {
+                warn!(target: "read_db", "upload session with id \"{session_id}\" has chunk with invalid id");
+                return Err(Box::new(DbError::ParseErr));
+            }
+        };
+
+        match db_session.try_into_upload_session(chunk_ids) {
+            Ok(session) => Ok(session),
+            Err(_) => Err(Box::new(DbError::ParseErr))
+        }
+    }
+
+    /// Records a received chunk of an upload session and extends the session's
+    /// expiry. A chunk that was already received is replaced.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_upload_chunk<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, session_id: S, chunk_id: u32, size: u64, sha256: S, ttl: u64) -> Result<UploadSession> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+        let session_id: String = session_id.into();
+
+        let before = self.read_upload_session(&repository_id, &channel_id, &release_id, &session_id)?;
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO upload_chunk(session, id, size, sha256)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![session_id, chunk_id, size, sha256.into()]
+        )?;
+        transaction.execute(
+            "UPDATE upload_session SET expires_at=?2 WHERE id=?1",
+            params![session_id, unix_time() + ttl]
+        )?;
+        let session = self.read_upload_session(&repository_id, &channel_id, &release_id, &session_id)?;
+        self.audit("add_upload_chunk", format!("/{repository_id}/{channel_id}/{release_id}/uploads/{session_id}/{chunk_id}"), to_json(&before), to_json(&session))?;
+        transaction.commit()?;
+
+        Ok(session)
+    }
+
+    /// Creates an artifact out of the file assembled from an upload session at
+    /// `path`, and deletes the session. Either both happen or neither does.
+    #[allow(clippy::too_many_arguments)]
+    pub fn finalize_upload_session<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, session_id: S, path: S, size: u64, hashes: Hashes) -> Result<Artifact> {
+        let repository_id: String = repository_id.into();
+        let channel_id: String = channel_id.into();
+        let release_id: String = release_id.into();
+        let session_id: String = session_id.into();
+
+        let session = self.read_upload_session(&repository_id, &channel_id, &release_id, &session_id)?;
+        if DbRelease::read(self, &repository_id, &channel_id, &release_id).is_err() {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let artifact = self.insert_artifact(&repository_id, &channel_id, &release_id, session.name().to_string(), path.into(), session.artifact_type(), size, &hashes)?;
+        self.remove_upload_session(&session_id)?;
+        transaction.commit()?;
+
+        Ok(artifact)
+    }
+
+    pub fn delete_upload_session<S: Into<String>>(&self, session_id: S) -> Result<()> {
+        let session_id: String = session_id.into();
+
+        let transaction = self.connection.unchecked_transaction()?;
+        self.remove_upload_session(&session_id)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Deletes an upload session and its chunks. Has to be called in a transaction.
+    fn remove_upload_session(&self, session_id: &str) -> Result<()> {
+        let target = match self.connection.query_row(
+            "SELECT repository, channel, release FROM upload_session WHERE id=?1",
+            params![session_id],
+            |row| Ok(format!("/{}/{}/{}/uploads/{session_id}", row.get::<usize, String>(0)?, row.get::<usize, String>(1)?, row.get::<usize, String>(2)?))
+        ) {
+            Ok(target) => target,
+            Err(_) => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        self.connection.execute("DELETE FROM upload_chunk WHERE session=?1", params![session_id])?;
+        self.connection.execute("DELETE FROM upload_session WHERE id=?1", params![session_id])?;
+        self.audit("delete_upload_session", target, None, None)?;
+
+        Ok(())
+    }
+
+    /// Deletes all expired upload sessions, returning their ids.
+    pub fn delete_expired_upload_sessions(&self) -> Result<Vec<String>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id FROM upload_session WHERE expires_at<?1"
+        )?;
+        let session_ids: std::result::Result<Vec<_>, _> = statement
+            .query_map(params![unix_time()], |row| row.get::<usize, String>(0))?
+            .collect();
+        let session_ids = session_ids?;
+
+        for session_id in &session_ids {
+            self.delete_upload_session(session_id)?;
+        }
+
+        Ok(session_ids)
+    }
+
+    /// Creates a token with the given grants. Returns it along with its secret,
+    /// which is only stored hashed and can't be recovered later.
+    pub fn create_token<S: Into<String>>(&self, name: S, grants: Vec<Grant>) -> Result<(Token, String)> {
+        let secret = auth::generate_secret();
+
+        let transaction = self.connection.unchecked_transaction()?;
+        let db_token = DbToken {
+            id: DbToken::next_id(self)?,
+            name: name.into(),
+            created_at: unix_time(),
+            revoked_at: None
+        };
+        db_token.insert(self, auth::hash_secret(&secret))?;
+        for grant in &grants {
+            let db_grant = DbTokenGrant {
+                repository: grant.repository().to_string(),
+                channel: grant.channel().to_string(),
+                action: grant.action().into()
+            };
+            db_grant.insert(self, db_token.id)?;
+        }
+        let token_id = db_token.id;
+        let token = self.token_with_grants(db_token)?;
+        self.audit("create_token", format!("/tokens/{token_id}"), None, to_json(&token))?;
+        transaction.commit()?;
+
+        Ok((token, secret))
+    }
+
+    /// Reads every token, including revoked ones.
+    pub fn read_tokens(&self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        for db_token in DbToken::read_all(self)? {
+            tokens.push(self.token_with_grants(db_token)?);
+        }
+
+        Ok(tokens)
+    }
+
+    /// Revokes a token. It is kept, so it still shows up in listings.
+    pub fn revoke_token(&self, token_id: u32) -> Result<Token> {
+        let before = match DbToken::read(self, token_id) {
+            Ok(db_token) if db_token.revoked_at.is_none() => self.token_with_grants(db_token)?,
+            _ => return Err(Box::new(DbError::NoSuchKey))
+        };
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE token SET revoked_at=?2 WHERE id=?1",
+            params![token_id, unix_time()]
+        )?;
+        let token = self.token_with_grants(DbToken::read(self, token_id)?)?;
+        self.audit("revoke_token", format!("/tokens/{token_id}"), to_json(&before), to_json(&token))?;
+        transaction.commit()?;
+
+        Ok(token)
+    }
+
+    /// Reads the paths of stored files whose size or any checksum is unknown,
+    /// from both current artifacts and their revisions.
+    pub fn read_paths_without_hashes(&self) -> Result<Vec<String>> {
+        let mut statement = self.connection.prepare(
+            "SELECT path FROM artifact WHERE size IS NULL OR sha1 IS NULL OR sha256 IS NULL OR sha512 IS NULL
+            UNION SELECT path FROM artifact_revision WHERE size IS NULL OR sha1 IS NULL OR sha256 IS NULL OR sha512 IS NULL"
         )?;
-        connection.execute(
-            "CREATE TABLE IF NOT EXISTS release (
-                id          TEXT NOT NULL,
-                repository  TEXT NOT NULL,
-                channel     TEXT NOT NULL,
-                name        TEXT NOT NULL,
-                created_at  INTEGER NOT NULL,
-                CONSTRAINT key PRIMARY KEY (id, repository, channel),
-                FOREIGN KEY(repository) REFERENCES repository(id),
-                FOREIGN KEY(channel) REFERENCES channel(id)
-            )",
-            ()
+        let paths = statement.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(paths)
+    }
+
+    /// Records the size and checksums of a stored file on every artifact and
+    /// revision pointing to it.
+    pub fn set_file_hashes(&self, path: &str, size: u64, hashes: &Hashes) -> Result<()> {
+        let artifacts = self.connection
+            .prepare("SELECT repository, channel, release, id FROM artifact WHERE path=?1")?
+            .query_map(params![path], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, u32>(3)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let revisions = self.connection
+            .prepare("SELECT repository, channel, release, artifact, revision FROM artifact_revision WHERE path=?1")?
+            .query_map(params![path], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, u32>(3)?, row.get::<_, u32>(4)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let transaction = self.connection.unchecked_transaction()?;
+        for (repository_id, channel_id, release_id, artifact_id) in artifacts {
+            let before = self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, true, true)?;
+            transaction.execute(
+                "UPDATE artifact SET
+                    size=?5,
+                    sha1=?6,
+                    sha256=?7,
+                    sha512=?8
+                WHERE
+                    id=?1 AND
+                    repository=?2 AND
+                    channel=?3 AND
+                    release=?4",
+                params![artifact_id, repository_id, channel_id, release_id, size, hashes.sha1(), hashes.sha256(), hashes.sha512()]
+            )?;
+            let artifact = self.read_artifact(&repository_id, &channel_id, &release_id, artifact_id, true, true)?;
+            self.audit("set_artifact_hashes", format!("/{repository_id}/{channel_id}/{release_id}/{artifact_id}"), to_json(&before), to_json(&artifact))?;
+        }
+        for (repository_id, channel_id, release_id, artifact_id, revision) in revisions {
+            let before = self.read_artifact_revision(&repository_id, &channel_id, &release_id, artifact_id, revision, true, true)?;
+            transaction.execute(
+                "UPDATE artifact_revision SET
+                    size=?6,
+                    sha1=?7,
+                    sha256=?8,
+                    sha512=?9
+                WHERE
+                    revision=?1 AND
+                    repository=?2 AND
+                    channel=?3 AND
+                    release=?4 AND
+                    artifact=?5",
+                params![revision, repository_id, channel_id, release_id, artifact_id, size, hashes.sha1(), hashes.sha256(), hashes.sha512()]
+            )?;
+            let artifact_revision = self.read_artifact_revision(&repository_id, &channel_id, &release_id, artifact_id, revision, true, true)?;
+            self.audit("set_artifact_revision_hashes", format!("/{repository_id}/{channel_id}/{release_id}/{artifact_id}/revisions/{revision}"), to_json(&before), to_json(&artifact_revision))?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Reads the audit log, newest first.
+    pub fn read_audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, token, actor, action, target, created_at, before_json, after_json
+            FROM audit_log
+            WHERE
+                (?1 IS NULL OR token=?1) AND
+                (?2 IS NULL OR actor=?2) AND
+                (?3 IS NULL OR action=?3) AND
+                (?4 IS NULL OR target=?4 OR substr(target, 1, length(?4) + 1)=?4 || '/') AND
+                (?5 IS NULL OR created_at>=?5) AND
+                (?6 IS NULL OR created_at<=?6) AND
+                (?7 IS NULL OR id<?7)
+            ORDER BY id DESC
+            LIMIT ?8"
         )?;
-        connection.execute(
-            "CREATE TABLE IF NOT EXISTS artifact (
-                id          INTEGER NOT NULL,
-                repository  TEXT NOT NULL,
-                channel     TEXT NOT NULL,
-                release     INTEGER NOT NULL,
-                name        TEXT NOT NULL,
-                path        TEXT NOT NULL,
-                type        INTEGER NOT NULL,
-                CONSTRAINT key PRIMARY KEY (id, repository, channel, release),
-                FOREIGN KEY(repository) REFERENCES repository(id),
-                FOREIGN KEY(channel) REFERENCES channel(id),
-                FOREIGN KEY(release) REFERENCES release(id)
-            )",
-            ()
+
+        let db_entries = statement.query_map(
+            params![filter.token, filter.actor, filter.action, filter.target, filter.since, filter.until, filter.before, filter.limit()],
+            DbAuditEntry::from_row
+        )?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut entries = Vec::new();
+        for db_entry in db_entries {
+            match db_entry.try_into_audit_entry() {
+                Ok(entry) => entries.push(entry),
+                Err(_) => return Err(Box::new(DbError::ParseErr))
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Records a change in the audit log. Must be called inside the
+    /// transaction making the change, so it is only logged if it's committed.
+    fn audit(&self, action: &str, target: String, before: Option<String>, after: Option<String>) -> Result<()> {
+        let (token, actor) = match &self.actor {
+            Some((token, actor)) => (*token, Some(actor)),
+            None => (None, None)
+        };
+        self.connection.execute(
+            "INSERT INTO audit_log(token, actor, action, target, created_at, before_json, after_json)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![token, actor, action, target, unix_time(), before, after]
         )?;
 
         Ok(())
     }
 
-    pub fn read_artifact<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, artifact_id: u32) -> Result<Artifact> {
+    /// Looks up the token with the given secret, along with its grants.
+    /// Revoked tokens are not found.
+    pub fn authenticate_token<S: Into<String>>(&self, secret: S) -> Result<Token> {
+        match DbToken::read_by_hash(self, auth::hash_secret(&secret.into())) {
+            Ok(db_token) => self.token_with_grants(db_token),
+            Err(_) => Err(Box::new(DbError::NoSuchKey))
+        }
+    }
+
+    fn token_with_grants(&self, db_token: DbToken) -> Result<Token> {
+        let mut grants = Vec::new();
+        for db_grant in DbTokenGrant::read_all(self, db_token.id)? {
+            match db_grant.try_into_grant() {
+                Ok(grant) => grants.push(grant),
+                Err(_) => {
+                    warn!(target: "read_db", "token with id \"{}\" has grant with invalid action", db_token.id);
+                    return Err(Box::new(DbError::ParseErr));
+                }
+            }
+        }
+
+        Ok(db_token.into_token(grants))
+    }
+
+    /// Reads an artifact. Artifacts in private channels are only returned if
+    /// `include_private` is set, and artifacts of drafts if `include_drafts` is.
+    pub fn read_artifact<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, artifact_id: u32, include_private: bool, include_drafts: bool) -> Result<Artifact> {
         let repository_id: String = repository_id.into();
         let channel_id: String = channel_id.into();
         let release_id: String = release_id.into();
 
-        let db_artifact = match DbArtifact::read(&self, &repository_id, &channel_id, &release_id, artifact_id) {
+        if !include_private {
+            match DbChannel::read(self, &repository_id, &channel_id) {
+                Ok(db_channel) if !db_channel.private => {},
+                _ => return Err(Box::new(DbError::NoSuchKey))
+            }
+        }
+        if !include_drafts {
+            match DbRelease::read(self, &repository_id, &channel_id, &release_id) {
+                Ok(db_release) if db_release.state == u32::from(ReleaseState::Published) => {},
+                _ => return Err(Box::new(DbError::NoSuchKey))
+            }
+        }
+
+        let db_artifact = match DbArtifact::read(self, &repository_id, &channel_id, &release_id, artifact_id) {
             Ok(db_artifact) => db_artifact,
             Err(_) => {
                 return Err(Box::new(DbError::NoSuchKey));
@@ -120,24 +1278,40 @@ impl ReleaseDatabase {
         }
     }
 
-    pub fn read_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S) -> Result<Release> {
+    /// Reads a release. Releases in private channels are only returned if
+    /// `include_private` is set, and drafts if `include_drafts` is.
+    pub fn read_release<S: Into<String>>(&self, repository_id: S, channel_id: S, release_id: S, include_private: bool, include_drafts: bool) -> Result<Release> {
         let repository_id: String = repository_id.into();
         let channel_id: String = channel_id.into();
         let release_id: String = release_id.into();
 
-        let db_release = match DbRelease::read(&self, &repository_id, &channel_id, &release_id) {
+        if !include_private {
+            match DbChannel::read(self, &repository_id, &channel_id) {
+                Ok(db_channel) if !db_channel.private => {},
+                _ => return Err(Box::new(DbError::NoSuchKey))
+            }
+        }
+
+        let db_release = match DbRelease::read(self, &repository_id, &channel_id, &release_id) {
             Ok(db_release) => db_release,
             Err(_) => {
                 return Err(Box::new(DbError::NoSuchKey));
             }
         };
+        if !include_drafts && db_release.state != u32::from(ReleaseState::Published) {
+            return Err(Box::new(DbError::NoSuchKey));
+        }
 
         let mut artifact_statement = self.connection.prepare(
-            "SELECT id FROM artifact WHERE release=?1"
+            "SELECT id FROM artifact
+            WHERE
+                repository=?1 AND
+                channel=?2 AND
+                release=?3"
         )?;
 
         let artifact_ids: std::result::Result<Vec<_>, _> = artifact_statement
-            .query_map(params![release_id], |row| row.get::<usize, u32>(0))?
+            .query_map(params![repository_id, channel_id, release_id], |row| row.get::<usize, u32>(0))?
             .collect();
         let artifact_ids = match artifact_ids {
             Ok(artifact_ids) => artifact_ids,
@@ -149,7 +1323,7 @@ impl ReleaseDatabase {
 
         let artifacts: std::result::Result<Vec<_>, _> = artifact_ids
             .into_iter()
-            .map(|id| self.read_artifact(&repository_id, &channel_id, &release_id, id))
+            .map(|id| self.read_artifact(&repository_id, &channel_id, &release_id, id, true, true))
             .collect();
         let artifacts = match artifacts {
             Ok(artifacts) => artifacts,
@@ -165,23 +1339,31 @@ impl ReleaseDatabase {
         }
     }
 
-    pub fn read_channel<S: Into<String>>(&self, repository_id: S, channel_id: S) -> Result<Channel> {
+    /// Reads a channel. Yanked releases are never listed, draft releases only
+    /// if `include_drafts` is set. Private channels are only returned at all
+    /// if `include_private` is set.
+    pub fn read_channel<S: Into<String>>(&self, repository_id: S, channel_id: S, include_private: bool, include_drafts: bool) -> Result<Channel> {
         let repository_id: String = repository_id.into();
         let channel_id: String = channel_id.into();
 
-        let db_channel = match DbChannel::read(&self, &repository_id, &channel_id) {
-            Ok(db_channel) => db_channel,
-            Err(_) => {
+        let db_channel = match DbChannel::read(self, &repository_id, &channel_id) {
+            Ok(db_channel) if include_private || !db_channel.private => db_channel,
+            _ => {
                 return Err(Box::new(DbError::NoSuchKey));
             }
         };
 
         let mut release_statement = self.connection.prepare(
-            "SELECT id FROM release WHERE channel=?1"
+            "SELECT id FROM release
+            WHERE
+                repository=?1 AND
+                channel=?2 AND
+                yanked=0 AND
+                (state=?3 OR ?4)"
         )?;
 
         let release_ids: std::result::Result<Vec<_>, _> = release_statement
-            .query_map(params![channel_id], |row| row.get::<usize, String>(0))?
+            .query_map(params![repository_id, channel_id, u32::from(ReleaseState::Published), include_drafts], |row| row.get::<usize, String>(0))?
             .collect();
         let release_ids = match release_ids {
             Ok(release_ids) => release_ids,
@@ -193,7 +1375,7 @@ impl ReleaseDatabase {
 
         let releases: std::result::Result<Vec<_>, _> = release_ids
             .into_iter()
-            .map(|id| self.read_release(repository_id.to_string(), channel_id.to_string(), id))
+            .map(|id| self.read_release(repository_id.to_string(), channel_id.to_string(), id, include_private, include_drafts))
             .collect();
         let releases = match releases {
             Ok(releases) => releases,
@@ -209,10 +1391,12 @@ impl ReleaseDatabase {
         }
     }
 
-    pub fn read_repository<S: Into<String>>(&self, repository_id: S) -> Result<Repository> {
+    /// Reads a repository. Private channels are only listed if `include_private`
+    /// returns true for their id, and drafts in a channel if `include_drafts` does.
+    pub fn read_repository<S: Into<String>, F: Fn(&str) -> bool, G: Fn(&str) -> bool>(&self, repository_id: S, include_private: F, include_drafts: G) -> Result<Repository> {
         let repository_id: String = repository_id.into();
 
-        let db_repository = match DbRepository::read(&self, &repository_id) {
+        let db_repository = match DbRepository::read(self, &repository_id) {
             Ok(db_repository) => db_repository,
             Err(_) => {
                 return Err(Box::new(DbError::NoSuchKey));
@@ -220,11 +1404,11 @@ impl ReleaseDatabase {
         };
 
         let mut channel_statement = self.connection.prepare(
-            "select id FROM channel WHERE repository=?1"
+            "select id, private FROM channel WHERE repository=?1"
         )?;
 
         let channel_ids: std::result::Result<Vec<_>, _> = channel_statement
-            .query_map(params![repository_id], |row| row.get::<usize, String>(0))?
+            .query_map(params![repository_id], |row| Ok((row.get::<usize, String>(0)?, row.get::<usize, bool>(1)?)))?
             .collect();
         let channel_ids = match channel_ids {
             Ok(channel_ids) => channel_ids,
@@ -236,7 +1420,11 @@ impl ReleaseDatabase {
 
         let channels: std::result::Result<Vec<_>, _> = channel_ids
             .into_iter()
-            .map(|id| self.read_channel(repository_id.to_string(), id))
+            .filter(|(id, private)| !private || include_private(id))
+            .map(|(id, _)| {
+                let include_drafts = include_drafts(&id);
+                self.read_channel(repository_id.to_string(), id, true, include_drafts)
+            })
             .collect();
         let channels = match channels {
             Ok(channels) => channels,
@@ -253,6 +1441,338 @@ impl ReleaseDatabase {
     }
 }
 
+/// Keys end up in URLs and in paths under the data directory, so only allow
+/// characters that are safe in both. Names starting with a dot are reserved
+/// for the server's own directories.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('.')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Repository keys must also not be reserved, or their channels would be
+/// shadowed by the server's own routes.
+fn is_valid_repository_key(key: &str) -> bool {
+    is_valid_key(key) && !RESERVED_REPOSITORY_KEYS.contains(&key)
+}
+
+/// Adds a column to a table created by an older version of the schema.
+fn migrate_column(connection: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns: std::result::Result<Vec<_>, _> = statement
+        .query_map((), |row| row.get::<usize, String>(1))?
+        .collect();
+
+    if !columns?.iter().any(|c| c == column) {
+        connection.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"), ())?;
+    }
+
+    Ok(())
+}
+
+/// The check for an existing key runs before the write, so a concurrent request
+/// can create the same key in between. Its insert then hits the primary key,
+/// which is reported the same as if the check had caught it.
+fn key_exists_on_conflict(error: rusqlite::Error) -> Box<dyn Error> {
+    match error.sqlite_error() {
+        Some(e) if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY => Box::new(DbError::KeyExists),
+        _ => Box::new(error)
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_string(value).ok()
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+struct DbAuditEntry {
+    id: u64,
+    token: Option<u32>,
+    actor: Option<String>,
+    action: String,
+    target: String,
+    created_at: u64,
+    before_json: Option<String>,
+    after_json: Option<String>
+}
+
+impl DbAuditEntry {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<DbAuditEntry> {
+        Ok(DbAuditEntry {
+            id: row.get(0)?,
+            token: row.get(1)?,
+            actor: row.get(2)?,
+            action: row.get(3)?,
+            target: row.get(4)?,
+            created_at: row.get(5)?,
+            before_json: row.get(6)?,
+            after_json: row.get(7)?
+        })
+    }
+
+    fn try_into_audit_entry(self) -> std::result::Result<AuditEntry, ()> {
+        let parse = |json: Option<String>| json.map(|json| serde_json::from_str(&json)).transpose().map_err(|_| ());
+        Ok(AuditEntry::new(self.id, self.token, self.actor, self.action, self.target, self.created_at, parse(self.before_json)?, parse(self.after_json)?))
+    }
+}
+
+struct DbToken {
+    id: u32,
+    name: String,
+    created_at: u64,
+    revoked_at: Option<u64>
+}
+
+impl DbToken {
+    fn read(db: &ReleaseDatabase, token_id: u32) -> Result<DbToken> {
+        let mut statement = db.connection.prepare(
+            "SELECT id, name, created_at, revoked_at FROM token
+            WHERE
+                id=?1"
+        )?;
+
+        let db_token = statement.query_row(params![token_id], Self::from_row)?;
+
+        Ok(db_token)
+    }
+
+    /// Reads the token with the given hash, unless it was revoked.
+    fn read_by_hash<S: Into<String>>(db: &ReleaseDatabase, hash: S) -> Result<DbToken> {
+        let mut statement = db.connection.prepare(
+            "SELECT id, name, created_at, revoked_at FROM token
+            WHERE
+                hash=?1 AND
+                revoked_at IS NULL"
+        )?;
+
+        let db_token = statement.query_row(params![hash.into()], Self::from_row)?;
+
+        Ok(db_token)
+    }
+
+    fn read_all(db: &ReleaseDatabase) -> Result<Vec<DbToken>> {
+        let mut statement = db.connection.prepare(
+            "SELECT id, name, created_at, revoked_at FROM token ORDER BY id"
+        )?;
+
+        let db_tokens = statement.query_map((), Self::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(db_tokens)
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<DbToken> {
+        Ok(DbToken {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_at: row.get(2)?,
+            revoked_at: row.get(3)?
+        })
+    }
+
+    fn insert<S: Into<String>>(&self, db: &ReleaseDatabase, hash: S) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO token(id, name, hash, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![self.id, self.name, hash.into(), self.created_at]
+        )?;
+
+        Ok(())
+    }
+
+    /// Revoked tokens are kept rather than deleted, so their ids are never reused.
+    fn next_id(db: &ReleaseDatabase) -> Result<u32> {
+        let id = db.connection.query_row(
+            "SELECT COALESCE(MAX(id) + 1, 1) FROM token",
+            (),
+            |row| row.get(0)
+        )?;
+
+        Ok(id)
+    }
+
+    fn into_token(self, grants: Vec<Grant>) -> Token {
+        let token = Token::new(self.id, self.name, self.created_at, grants);
+        match self.revoked_at {
+            Some(revoked_at) => token.with_revoked_at(revoked_at),
+            None => token
+        }
+    }
+}
+
+struct DbTokenGrant {
+    repository: String,
+    channel: String,
+    action: u32
+}
+
+impl DbTokenGrant {
+    fn read_all(db: &ReleaseDatabase, token_id: u32) -> Result<Vec<DbTokenGrant>> {
+        let mut statement = db.connection.prepare(
+            "SELECT repository, channel, action FROM token_grant
+            WHERE
+                token=?1"
+        )?;
+
+        let db_grants = statement.query_map(params![token_id], |row| {
+            Ok(DbTokenGrant {
+                repository: row.get(0)?,
+                channel: row.get(1)?,
+                action: row.get(2)?
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(db_grants)
+    }
+
+    fn insert(&self, db: &ReleaseDatabase, token_id: u32) -> Result<()> {
+        db.connection.execute(
+            "INSERT OR IGNORE INTO token_grant(token, repository, channel, action) VALUES (?1, ?2, ?3, ?4)",
+            params![token_id, self.repository, self.channel, self.action]
+        )?;
+
+        Ok(())
+    }
+
+    fn try_into_grant(self) -> std::result::Result<Grant, ()> {
+        let action = Action::try_from(self.action)?;
+        Ok(Grant::new(self.repository, self.channel, action))
+    }
+}
+
+struct DbArtifactRevision {
+    revision: u32,
+    name: String,
+    path: String,
+    artifact_type: u32,
+    replaced_at: u64,
+    size: Option<u64>,
+    sha1: Option<String>,
+    sha256: Option<String>,
+    sha512: Option<String>,
+    content_type: Option<String>
+}
+
+impl DbArtifactRevision {
+    fn read<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S, artifact_id: u32, revision: u32) -> Result<DbArtifactRevision> {
+        let mut statement = db.connection.prepare(
+            "SELECT revision, name, path, type, replaced_at, size, sha1, sha256, sha512, content_type
+            FROM artifact_revision
+            WHERE
+                revision=?1 AND
+                repository=?2 AND
+                channel=?3 AND
+                release=?4 AND
+                artifact=?5"
+        )?;
+
+        let db_revision = statement.query_row(params![revision, repository_id.into(), channel_id.into(), release_id.into(), artifact_id], |row| {
+            Ok(DbArtifactRevision {
+                revision: row.get(0)?,
+                name: row.get(1)?,
+                path: row.get(2)?,
+                artifact_type: row.get(3)?,
+                replaced_at: row.get(4)?,
+                size: row.get(5)?,
+                sha1: row.get(6)?,
+                sha256: row.get(7)?,
+                sha512: row.get(8)?,
+                content_type: row.get(9)?
+            })
+        })?;
+
+        Ok(db_revision)
+    }
+
+    fn next_id<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S, artifact_id: u32) -> Result<u32> {
+        let mut statement = db.connection.prepare(
+            "SELECT COALESCE(MAX(revision) + 1, 0)
+            FROM artifact_revision
+            WHERE
+                repository=?1 AND
+                channel=?2 AND
+                release=?3 AND
+                artifact=?4"
+        )?;
+
+        let id = statement.query_row(params![repository_id.into(), channel_id.into(), release_id.into(), artifact_id], |row| row.get(0))?;
+
+        Ok(id)
+    }
+
+    fn insert<S: Into<String>>(&self, db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S, artifact_id: u32) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO artifact_revision(revision, repository, channel, release, artifact, name, path, type, replaced_at, size, sha1, sha256, sha512, content_type)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![self.revision, repository_id.into(), channel_id.into(), release_id.into(), artifact_id, self.name, self.path, self.artifact_type, self.replaced_at, self.size, self.sha1, self.sha256, self.sha512, self.content_type]
+        )?;
+
+        Ok(())
+    }
+
+    fn try_into_artifact_revision(self) -> std::result::Result<ArtifactRevision, ()> {
+        let artifact_type = ArtifactType::try_from(self.artifact_type)?;
+        Ok(ArtifactRevision::new(self.revision, self.name, self.path, artifact_type, self.replaced_at)
+            .with_file(self.size, Hashes::new(self.sha1, self.sha256, self.sha512))
+            .with_content_type(self.content_type))
+    }
+}
+
+struct DbUploadSession {
+    id: String,
+    name: String,
+    artifact_type: u32,
+    size: Option<u64>,
+    sha256: String,
+    expires_at: u64
+}
+
+impl DbUploadSession {
+    fn read<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S, session_id: S) -> Result<DbUploadSession> {
+        let mut statement = db.connection.prepare(
+            "SELECT id, name, type, size, sha256, expires_at
+            FROM upload_session
+            WHERE
+                id=?1 AND
+                repository=?2 AND
+                channel=?3 AND
+                release=?4"
+        )?;
+
+        let db_session = statement.query_row(params![session_id.into(), repository_id.into(), channel_id.into(), release_id.into()], |row| {
+            Ok(DbUploadSession {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                artifact_type: row.get(2)?,
+                size: row.get(3)?,
+                sha256: row.get(4)?,
+                expires_at: row.get(5)?
+            })
+        })?;
+
+        Ok(db_session)
+    }
+
+    fn insert<S: Into<String>>(&self, db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO upload_session(id, repository, channel, release, name, type, size, sha256, expires_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![self.id, repository_id.into(), channel_id.into(), release_id.into(), self.name, self.artifact_type, self.size, self.sha256, self.expires_at]
+        )?;
+
+        Ok(())
+    }
+
+    fn try_into_upload_session(self, chunks: Vec<u32>) -> std::result::Result<UploadSession, ()> {
+        let artifact_type = ArtifactType::try_from(self.artifact_type)?;
+        Ok(UploadSession::new(self.id, self.name, artifact_type, self.size, self.sha256, self.expires_at, chunks))
+    }
+}
+
 struct DbRepository {
     id: String
 }
@@ -274,19 +1794,29 @@ impl DbRepository {
         Ok(db_repository)
     }
 
+    fn insert(&self, db: &ReleaseDatabase) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO repository(id) VALUES (?1)",
+            params![self.id]
+        ).map_err(key_exists_on_conflict)?;
+
+        Ok(())
+    }
+
     fn try_into_repository(self, channels: Vec<Channel>) -> std::result::Result<Repository, ()> {
         Ok(Repository::new(self.id, channels))
     }
 }
 
 struct DbChannel {
-    id: String
+    id: String,
+    private: bool
 }
 
 impl DbChannel {
     fn read<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S) -> Result<DbChannel> {
         let mut statement = db.connection.prepare(
-            "SELECT cha.id 
+            "SELECT cha.id, cha.private
             FROM channel AS cha
             INNER JOIN repository AS rep ON rep.id=cha.repository
             WHERE
@@ -296,28 +1826,42 @@ impl DbChannel {
 
         let db_channel = statement.query_row(params![channel_id.into(), repository_id.into()], |row| {
             Ok(DbChannel {
-                id: row.get(0)?
+                id: row.get(0)?,
+                private: row.get(1)?
             })
         })?;
 
         Ok(db_channel)
     }
 
+    fn insert<S: Into<String>>(&self, db: &ReleaseDatabase, repository_id: S) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO channel(id, repository, private) VALUES (?1, ?2, ?3)",
+            params![self.id, repository_id.into(), self.private]
+        ).map_err(key_exists_on_conflict)?;
+
+        Ok(())
+    }
+
     fn try_into_channel(self, releases: Vec<Release>) -> std::result::Result<Channel, ()> {
-        Ok(Channel::new(self.id, releases))
+        Ok(Channel::new(self.id, releases).with_private(self.private))
     }
 }
 
 struct DbRelease {
     id: String,
     name: String,
-    created_at: u64
+    created_at: u64,
+    yanked: bool,
+    yank_reason: Option<String>,
+    promoted_from: Option<String>,
+    state: u32
 }
 
 impl DbRelease {
     fn read<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S) -> Result<DbRelease> {
         let mut statement = db.connection.prepare(
-            "SELECT rel.id, rel.name, rel.created_at
+            "SELECT rel.id, rel.name, rel.created_at, rel.yanked, rel.yank_reason, rel.promoted_from, rel.state
             FROM release AS rel
             INNER JOIN channel AS cha ON cha.id=rel.channel
             INNER JOIN repository AS rep ON rep.id=rel.repository
@@ -331,15 +1875,37 @@ impl DbRelease {
             Ok(DbRelease {
                 id: row.get(0)?,
                 name: row.get(1)?,
-                created_at: row.get(2)?
+                created_at: row.get(2)?,
+                yanked: row.get(3)?,
+                yank_reason: row.get(4)?,
+                promoted_from: row.get(5)?,
+                state: row.get(6)?
             })
         })?;
 
         Ok(db_release)
     }
 
+    fn insert<S: Into<String>>(&self, db: &ReleaseDatabase, repository_id: S, channel_id: S) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO release(id, repository, channel, name, created_at, state)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![self.id, repository_id.into(), channel_id.into(), self.name, self.created_at, self.state]
+        ).map_err(key_exists_on_conflict)?;
+
+        Ok(())
+    }
+
     fn try_into_release(self, artifacts: Vec<Artifact>) -> std::result::Result<Release, ()> {
-        Ok(Release::new(self.id, self.name, self.created_at, artifacts))
+        let state = ReleaseState::try_from(self.state)?;
+        let mut release = Release::new(self.id, self.name, self.created_at, artifacts).with_state(state);
+        if self.yanked {
+            release = release.with_yank(self.yank_reason);
+        }
+        if let Some(channel_id) = self.promoted_from {
+            release = release.with_promoted_from(channel_id);
+        }
+        Ok(release)
     }
 }
 
@@ -347,13 +1913,18 @@ struct DbArtifact {
     id: u32,
     name: String,
     path: String,
-    artifact_type: u32
+    artifact_type: u32,
+    size: Option<u64>,
+    sha1: Option<String>,
+    sha256: Option<String>,
+    sha512: Option<String>,
+    content_type: Option<String>
 }
 
 impl DbArtifact {
     fn read<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S, artifact_id: u32) -> Result<DbArtifact> {
         let mut statement = db.connection.prepare(
-            "SELECT art.id, art.name, art.path, art.type
+            "SELECT art.id, art.name, art.path, art.type, art.size, art.sha1, art.sha256, art.sha512, art.content_type
             FROM artifact AS art
             INNER JOIN release AS rel ON rel.id=art.release
             INNER JOIN channel AS cha ON cha.id=art.channel
@@ -370,16 +1941,48 @@ impl DbArtifact {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 path: row.get(2)?,
-                artifact_type: row.get(3)?
+                artifact_type: row.get(3)?,
+                size: row.get(4)?,
+                sha1: row.get(5)?,
+                sha256: row.get(6)?,
+                sha512: row.get(7)?,
+                content_type: row.get(8)?
             })
         })?;
 
         Ok(db_artifact)
     }
 
+    fn next_id<S: Into<String>>(db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S) -> Result<u32> {
+        let mut statement = db.connection.prepare(
+            "SELECT COALESCE(MAX(id) + 1, 0)
+            FROM artifact
+            WHERE
+                repository=?1 AND
+                channel=?2 AND
+                release=?3"
+        )?;
+
+        let id = statement.query_row(params![repository_id.into(), channel_id.into(), release_id.into()], |row| row.get(0))?;
+
+        Ok(id)
+    }
+
+    fn insert<S: Into<String>>(&self, db: &ReleaseDatabase, repository_id: S, channel_id: S, release_id: S) -> Result<()> {
+        db.connection.execute(
+            "INSERT INTO artifact(id, repository, channel, release, name, path, type, size, sha1, sha256, sha512, content_type)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![self.id, repository_id.into(), channel_id.into(), release_id.into(), self.name, self.path, self.artifact_type, self.size, self.sha1, self.sha256, self.sha512, self.content_type]
+        )?;
+
+        Ok(())
+    }
+
     fn try_into_artifact(self) -> std::result::Result<Artifact, ()> {
         if let Ok(artifact_type) = ArtifactType::try_from(self.artifact_type) {
-            Ok(Artifact::new(self.id, self.name, self.path, artifact_type))
+            Ok(Artifact::new(self.id, self.name, self.path, artifact_type)
+                .with_file(self.size, Hashes::new(self.sha1, self.sha256, self.sha512))
+                .with_content_type(self.content_type))
         } else {
             Err(())
         }
@@ -0,0 +1,210 @@
+use std::{error::Error, fs, sync::RwLock};
+
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use log::warn;
+
+use crate::{auth::Token, config::Jwt, db::unix_time};
+
+type Claims = serde_json::Map<String, serde_json::Value>;
+
+/// Verifies bearer JWTs against the keys in the configured JWKS file, and maps
+/// their claims to grants.
+pub struct JwtVerifier {
+    config: Jwt,
+    keys: RwLock<JwkSet>
+}
+
+impl JwtVerifier {
+    pub fn new(config: Jwt) -> Result<Self, Box<dyn Error>> {
+        let keys = read_jwks(&config)?;
+        Ok(Self {
+            config,
+            keys: RwLock::new(keys)
+        })
+    }
+
+    /// Reads the JWKS file again, so rotated keys are picked up without a
+    /// restart. The old keys are kept if the file can't be read.
+    pub fn reload(&self) {
+        match read_jwks(&self.config) {
+            Ok(keys) => *self.keys.write().unwrap() = keys,
+            Err(e) => warn!("Failed to reload JWKS: {}", e.to_string())
+        }
+    }
+
+    /// Checks the JWT's signature, issuer, audience and expiry, and returns a
+    /// token with the grants of every rule its claims match.
+    pub fn verify(&self, jwt: &str) -> Result<Token, Box<dyn Error>> {
+        let header = jsonwebtoken::decode_header(jwt)?;
+        let (key, algorithm) = {
+            let keys = self.keys.read().unwrap();
+            // Without a key id, the key is only unambiguous if there's just one.
+            let jwk = match &header.kid {
+                Some(kid) => keys.find(kid),
+                None if keys.keys.len() == 1 => keys.keys.first(),
+                None => None
+            }.ok_or("No matching key in JWKS")?;
+
+            let algorithm = match jwk.common.key_algorithm {
+                Some(key_algorithm) => key_algorithm.to_string().parse::<Algorithm>()?,
+                None => header.alg
+            };
+            (DecodingKey::from_jwk(jwk)?, algorithm)
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[self.config.issuer()]);
+        validation.set_audience(&[self.config.audience()]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        let claims = jsonwebtoken::decode::<Claims>(jwt, &key, &validation)?.claims;
+
+        let grants = self.config.rules().iter()
+            .filter(|rule| rule.matches(&claims))
+            .flat_map(|rule| rule.grants())
+            .collect();
+        let subject = claims.get("sub").and_then(|sub| sub.as_str()).unwrap_or(self.config.issuer());
+        let issued_at = claims.get("iat").and_then(|iat| iat.as_u64()).unwrap_or_else(unix_time);
+
+        Ok(Token::external(format!("jwt:{subject}"), issued_at, grants))
+    }
+}
+
+/// Whether a bearer secret has the shape of a JWT rather than a stored token.
+pub fn is_jwt(secret: &str) -> bool {
+    secret.split('.').count() == 3
+}
+
+fn read_jwks(config: &Jwt) -> Result<JwkSet, Box<dyn Error>> {
+    let path = config.jwks_path();
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read JWKS from {}: {}", path.display(), e))?;
+    Ok(serde_json::from_str(&contents).map_err(|e| format!("Invalid JWKS in {}: {}", path.display(), e))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{EncodingKey, Header};
+    use ring::{rand::SystemRandom, signature::{Ed25519KeyPair, KeyPair}};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::auth::Action;
+
+    const ISSUER: &str = "https://ci.example.com";
+    const AUDIENCE: &str = "bta-release-api";
+
+    struct Key {
+        kid: &'static str,
+        encoding: EncodingKey,
+        jwk: Value
+    }
+
+    fn generate_key(kid: &'static str) -> Key {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        Key {
+            kid,
+            encoding: EncodingKey::from_ed_der(pkcs8.as_ref()),
+            jwk: json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "alg": "EdDSA",
+                "kid": kid,
+                "x": BASE64_URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref())
+            })
+        }
+    }
+
+    /// Writes the keys to a JWKS file and reads it into a verifier, with a
+    /// rule granting publish on mod/nightly to CI runs on main of bta/mod.
+    fn verifier(test: &str, keys: &[&Key]) -> JwtVerifier {
+        let path = std::env::temp_dir().join(format!("bta-release-api-{}-{test}.json", std::process::id()));
+        fs::write(&path, json!({ "keys": keys.iter().map(|key| &key.jwk).collect::<Vec<_>>() }).to_string()).unwrap();
+        let config: Jwt = serde_json::from_value(json!({
+            "jwks_path": path,
+            "issuer": ISSUER,
+            "audience": AUDIENCE,
+            "rules": [{ "claims": { "repository": "bta/mod", "ref": "refs/heads/main" }, "scopes": ["mod/nightly:publish"] }]
+        })).unwrap();
+        let verifier = JwtVerifier::new(config).unwrap();
+        fs::remove_file(path).unwrap();
+        verifier
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "sub": "repo:bta/mod:ref:refs/heads/main",
+            "exp": unix_time() + 600,
+            "repository": "bta/mod",
+            "ref": "refs/heads/main"
+        })
+    }
+
+    fn sign(key: &Key, kid: Option<&str>, claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = kid.map(str::to_string);
+        jsonwebtoken::encode(&header, claims, &key.encoding).unwrap()
+    }
+
+    #[test]
+    fn maps_valid_jwt_to_rule_grants() {
+        let key = generate_key("ci");
+        let verifier = verifier("valid", &[&key]);
+
+        let token = verifier.verify(&sign(&key, Some(key.kid), &claims())).unwrap();
+        assert_eq!(token.name(), "jwt:repo:bta/mod:ref:refs/heads/main");
+        assert_eq!(token.id(), None);
+        assert!(token.allows(Some("mod"), Some("nightly"), Action::Publish));
+        assert!(!token.allows(Some("mod"), Some("stable"), Action::Publish));
+
+        let mut claims = claims();
+        claims["ref"] = json!("refs/heads/feature");
+        let token = verifier.verify(&sign(&key, Some(key.kid), &claims)).unwrap();
+        assert!(token.grants().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let key = generate_key("ci");
+        let other = generate_key("other");
+        let verifier = verifier("signature", &[&key]);
+
+        assert!(verifier.verify(&sign(&other, Some(key.kid), &claims())).is_err());
+        assert!(verifier.verify(&sign(&other, Some(other.kid), &claims())).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_issuer_or_audience() {
+        let key = generate_key("ci");
+        let verifier = verifier("issuer", &[&key]);
+
+        let mut wrong_issuer = claims();
+        wrong_issuer["iss"] = json!("https://elsewhere.example.com");
+        assert!(verifier.verify(&sign(&key, Some(key.kid), &wrong_issuer)).is_err());
+
+        let mut wrong_audience = claims();
+        wrong_audience["aud"] = json!("another-api");
+        assert!(verifier.verify(&sign(&key, Some(key.kid), &wrong_audience)).is_err());
+    }
+
+    #[test]
+    fn rejects_expired_jwt() {
+        let key = generate_key("ci");
+        let verifier = verifier("expired", &[&key]);
+
+        let mut claims = claims();
+        claims["exp"] = json!(unix_time() - 3600);
+        assert!(verifier.verify(&sign(&key, Some(key.kid), &claims)).is_err());
+    }
+
+    #[test]
+    fn requires_kid_only_with_multiple_keys() {
+        let key = generate_key("ci");
+        let other = generate_key("other");
+
+        assert!(verifier("single_key", &[&key]).verify(&sign(&key, None, &claims())).is_ok());
+        assert!(verifier("multiple_keys", &[&key, &other]).verify(&sign(&key, None, &claims())).is_err());
+    }
+}
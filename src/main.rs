@@ -1,11 +1,21 @@
 use api::Api;
+use clap::Parser;
+use cli::Cli;
 use config::Config;
 use simple_logger::SimpleLogger;
 
 mod api;
+mod audit;
+mod auth;
+mod cli;
+mod conditional;
 mod release;
 mod db;
+mod jwt;
+mod range;
+mod rate_limit;
 mod config;
+mod storage;
 
 const PATHS: [&str; 2] = [
     "~/.config/btapi/config.toml",
@@ -14,18 +24,40 @@ const PATHS: [&str; 2] = [
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
     let mut config: Option<Config> = None;
     for path in PATHS {
-        if let Ok(cfg) = Config::open(shellexpand::full(path).unwrap().to_string()) {
-            config = Some(cfg);
-            println!("Using config file: {}", path);
-            break;
+        match Config::open(shellexpand::full(path).unwrap().to_string()) {
+            Ok(Some(cfg)) => {
+                config = Some(cfg);
+                eprintln!("Using config file: {}", path);
+                break;
+            },
+            Ok(None) => {},
+            Err(e) => {
+                eprintln!("Error: invalid config file {}: {}", path, e);
+                std::process::exit(1);
+            }
         }
     }
     let config = config.unwrap_or_default();
 
     SimpleLogger::new().with_level(config.log_level()).init().unwrap();
 
-    let api = Api::new(config);
-    api.run().await;
+    if let Some(command) = cli.command {
+        if let Err(e) = cli::run(&config, command) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    match Api::new(config) {
+        Ok(api) => api.run().await,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use std::{io::{self, SeekFrom}, ops::RangeInclusive, path::Path};
+
+use tokio::{fs::File, io::{AsyncRead, AsyncReadExt, AsyncSeekExt}};
+
+/// More ranges than this in one request are ignored and the whole file is
+/// sent instead, so a request can't make us open and send the file many times.
+const MAX_RANGES: usize = 32;
+
+/// What part of a file a request asked for with its `Range` header.
+#[derive(PartialEq, Eq, Debug)]
+pub enum ByteRanges {
+    Full,
+    Partial(Vec<RangeInclusive<u64>>),
+    Unsatisfiable
+}
+
+/// Parses a `Range` header for a file of `len` bytes. Units other than bytes
+/// are ignored, as are ranges starting past the end of the file, as long as
+/// at least one range can be satisfied.
+pub fn parse_range(header: &str, len: u64) -> ByteRanges {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return ByteRanges::Full
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return ByteRanges::Unsatisfiable
+        };
+        let range = if first.is_empty() {
+            // The last n bytes.
+            match last.parse::<u64>() {
+                Ok(0) => None,
+                Ok(suffix) => Some(len.saturating_sub(suffix)..=len.saturating_sub(1)),
+                Err(_) => return ByteRanges::Unsatisfiable
+            }
+        } else {
+            match (first.parse::<u64>(), last.parse::<u64>()) {
+                (Ok(first), _) if last.is_empty() => Some(first..=len.saturating_sub(1)),
+                (Ok(first), Ok(last)) if first <= last => Some(first..=last.min(len.saturating_sub(1))),
+                _ => return ByteRanges::Unsatisfiable
+            }
+        };
+        if let Some(range) = range.filter(|range| *range.start() < len) {
+            ranges.push(range);
+        }
+    }
+
+    if ranges.len() > MAX_RANGES {
+        ByteRanges::Full
+    } else if ranges.is_empty() {
+        ByteRanges::Unsatisfiable
+    } else {
+        ByteRanges::Partial(ranges)
+    }
+}
+
+/// Opens a reader over `range` of the file at `path`.
+pub async fn read_range(path: &Path, range: &RangeInclusive<u64>) -> io::Result<impl AsyncRead + Send + Unpin> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(*range.start())).await?;
+    Ok(file.take(range.end() - range.start() + 1))
+}
+
+/// Builds a `multipart/byteranges` body out of `ranges` of the file at `path`,
+/// returning the reader and the length of the body.
+pub async fn read_ranges(path: &Path, ranges: &[RangeInclusive<u64>], len: u64, content_type: &str, boundary: &str) -> io::Result<(Box<dyn AsyncRead + Send + Unpin>, u64)> {
+    let mut reader: Box<dyn AsyncRead + Send + Unpin> = Box::new(tokio::io::empty());
+    let mut body_len = 0;
+    for range in ranges {
+        let part_header = format!(
+            "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {}-{}/{len}\r\n\r\n",
+            range.start(), range.end()
+        );
+        body_len += part_header.len() as u64 + range.end() - range.start() + 1;
+        reader = Box::new(reader.chain(io::Cursor::new(part_header)).chain(read_range(path, range).await?));
+    }
+
+    let closing = format!("\r\n--{boundary}--\r\n");
+    body_len += closing.len() as u64;
+    Ok((Box::new(reader.chain(io::Cursor::new(closing))), body_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(parse_range("bytes=-100", 1000), ByteRanges::Partial(vec![900..=999]));
+        assert_eq!(parse_range("bytes=-5000", 1000), ByteRanges::Partial(vec![0..=999]));
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn parses_open_ended_ranges() {
+        assert_eq!(parse_range("bytes=500-", 1000), ByteRanges::Partial(vec![500..=999]));
+        assert_eq!(parse_range("bytes=0-", 1000), ByteRanges::Partial(vec![0..=999]));
+    }
+
+    #[test]
+    fn clamps_ranges_ending_past_eof() {
+        assert_eq!(parse_range("bytes=900-5000", 1000), ByteRanges::Partial(vec![900..=999]));
+    }
+
+    #[test]
+    fn ranges_starting_past_eof_are_unsatisfiable() {
+        assert_eq!(parse_range("bytes=1000-1100", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=2000-", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-0", 0), ByteRanges::Unsatisfiable);
+        // Only as long as no other range can be satisfied.
+        assert_eq!(parse_range("bytes=2000-, 0-9", 1000), ByteRanges::Partial(vec![0..=9]));
+    }
+
+    #[test]
+    fn parses_multiple_ranges() {
+        assert_eq!(parse_range("bytes=0-9, 20-29,-10", 1000), ByteRanges::Partial(vec![0..=9, 20..=29, 990..=999]));
+    }
+
+    #[test]
+    fn malformed_ranges_are_unsatisfiable() {
+        assert_eq!(parse_range("bytes=9-0", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=a-b", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=10", 1000), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn too_many_ranges_send_the_whole_file() {
+        let header = format!("bytes={}", (0..=MAX_RANGES).map(|i| format!("{i}-{i}")).collect::<Vec<_>>().join(","));
+        assert_eq!(parse_range(&header, 1000), ByteRanges::Full);
+
+        let header = format!("bytes={}", (0..MAX_RANGES).map(|i| format!("{i}-{i}")).collect::<Vec<_>>().join(","));
+        assert!(matches!(parse_range(&header, 1000), ByteRanges::Partial(ranges) if ranges.len() == MAX_RANGES));
+    }
+
+    #[test]
+    fn other_units_send_the_whole_file() {
+        assert_eq!(parse_range("items=0-9", 1000), ByteRanges::Full);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+
+use crate::config::RateLimit;
+
+/// Token bucket rate limiter with one bucket per client key.
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Takes a token from the client's bucket. If it's empty, returns how long
+    /// the client has to wait for the next one.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.limit.burst(),
+            updated_at: now
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.limit.per_second()).min(self.limit.burst());
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.limit.per_second()))
+        }
+    }
+
+    /// Forgets buckets that have refilled completely, as they are no different
+    /// from new ones.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.buckets.lock().unwrap().retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+            bucket.tokens + elapsed * self.limit.per_second() < self.limit.burst()
+        });
+    }
+}
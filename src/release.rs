@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
 type Id = u32;
@@ -15,32 +17,31 @@ impl Repository {
             channels: channels.iter().map(|c| c.id().to_string()).collect()
         }
     }
-
-    pub fn channels(&self) -> &Vec<String> {
-        &self.channels
-    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Channel {
     id: String,
-    releases: Vec<String>
+    releases: Vec<String>,
+    private: bool
 }
 
 impl Channel {
     pub fn new<S: Into<String>>(id: S, releases: Vec<Release>) -> Self {
         Self {
             id: id.into(),
-            releases: releases.iter().map(|r| r.id().to_string()).collect()
+            releases: releases.iter().map(|r| r.id().to_string()).collect(),
+            private: false
         }
     }
 
-    pub fn id(&self) -> &str {
-        &self.id
+    pub fn with_private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
     }
 
-    pub fn releases(&self) -> &Vec<String> {
-        &self.releases
+    pub fn id(&self) -> &str {
+        &self.id
     }
 }
 
@@ -49,7 +50,11 @@ pub struct Release {
     id: String,
     name: String,
     created_at: u64,
-    artifacts: Vec<String>
+    artifacts: Vec<String>,
+    yanked: bool,
+    yank_reason: Option<String>,
+    promoted_from: Option<String>,
+    state: ReleaseState
 }
 
 impl Release {
@@ -58,24 +63,60 @@ impl Release {
             id: id.into(),
             name: name.into(),
             created_at,
-            artifacts: artifacts.iter().map(|a| a.id().to_string()).collect()
+            artifacts: artifacts.iter().map(|a| a.id().to_string()).collect(),
+            yanked: false,
+            yank_reason: None,
+            promoted_from: None,
+            state: ReleaseState::Published
         }
     }
 
+    pub fn with_state(mut self, state: ReleaseState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn with_yank(mut self, reason: Option<String>) -> Self {
+        self.yanked = true;
+        self.yank_reason = reason;
+        self
+    }
+
+    /// Records the channel this release was promoted from.
+    pub fn with_promoted_from<S: Into<String>>(mut self, channel_id: S) -> Self {
+        self.promoted_from = Some(channel_id.into());
+        self
+    }
+
     pub fn id(&self) -> &str {
         &self.id
     }
+}
 
-    pub fn name(&self) -> &str {
-        &self.name
-    }
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseState {
+    Draft,
+    Published
+}
 
-    pub fn created_at(&self) -> &u64 {
-        &self.created_at
+impl From<ReleaseState> for u32 {
+    fn from(value: ReleaseState) -> Self {
+        match value {
+            ReleaseState::Draft => 0,
+            ReleaseState::Published => 1
+        }
     }
+}
+
+impl TryFrom<u32> for ReleaseState {
+    type Error = ();
 
-    pub fn artifacts(&self) -> &Vec<String> {
-        &self.artifacts
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Draft),
+            1 => Ok(Self::Published),
+            _ => Err(())
+        }
     }
 }
 
@@ -84,7 +125,11 @@ pub struct Artifact {
     id: Id,
     name: String,
     path: String,
-    artifact_type: ArtifactType
+    artifact_type: ArtifactType,
+    /// Size of the file in bytes. Unknown for files stored before it was recorded.
+    size: Option<u64>,
+    hashes: Hashes,
+    content_type: Option<String>
 }
 
 impl Artifact {
@@ -93,10 +138,25 @@ impl Artifact {
             id,
             name: name.into(),
             path: path.into(),
-            artifact_type
+            artifact_type,
+            size: None,
+            hashes: Hashes::default(),
+            content_type: None
         }
     }
 
+    pub fn with_file(mut self, size: Option<u64>, hashes: Hashes) -> Self {
+        self.size = size;
+        self.hashes = hashes;
+        self
+    }
+
+    /// Overrides the media type the file is served as.
+    pub fn with_content_type(mut self, content_type: Option<String>) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
     pub fn id(&self) -> Id {
         self.id
     }
@@ -109,9 +169,152 @@ impl Artifact {
         &self.path
     }
 
+    pub fn hashes(&self) -> &Hashes {
+        &self.hashes
+    }
+
+    /// Media type the file is served as, unless overridden the one of its artifact type.
+    pub fn content_type(&self) -> &str {
+        self.content_type.as_deref().unwrap_or(self.artifact_type.content_type())
+    }
+}
+
+/// An earlier file of an artifact, kept when the artifact's file is replaced.
+/// Its file's size, hashes and media type are recorded as for an [`Artifact`].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArtifactRevision {
+    revision: Id,
+    name: String,
+    path: String,
+    artifact_type: ArtifactType,
+    replaced_at: u64,
+    size: Option<u64>,
+    hashes: Hashes,
+    content_type: Option<String>
+}
+
+impl ArtifactRevision {
+    pub fn new<S: Into<String>>(revision: Id, name: S, path: S, artifact_type: ArtifactType, replaced_at: u64) -> Self {
+        Self {
+            revision,
+            name: name.into(),
+            path: path.into(),
+            artifact_type,
+            replaced_at,
+            size: None,
+            hashes: Hashes::default(),
+            content_type: None
+        }
+    }
+
+    pub fn with_file(mut self, size: Option<u64>, hashes: Hashes) -> Self {
+        self.size = size;
+        self.hashes = hashes;
+        self
+    }
+
+    /// Overrides the media type the file is served as.
+    pub fn with_content_type(mut self, content_type: Option<String>) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn hashes(&self) -> &Hashes {
+        &self.hashes
+    }
+
+    /// See [`Artifact::content_type`].
+    pub fn content_type(&self) -> &str {
+        self.content_type.as_deref().unwrap_or(self.artifact_type.content_type())
+    }
+}
+
+/// Hex encoded checksums of a file. Each is unknown for files stored before
+/// it was recorded.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Hashes {
+    sha1: Option<String>,
+    sha256: Option<String>,
+    sha512: Option<String>
+}
+
+impl Hashes {
+    pub fn new(sha1: Option<String>, sha256: Option<String>, sha512: Option<String>) -> Self {
+        Self {
+            sha1,
+            sha256,
+            sha512
+        }
+    }
+
+    pub fn sha1(&self) -> Option<&str> {
+        self.sha1.as_deref()
+    }
+
+    pub fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    pub fn sha512(&self) -> Option<&str> {
+        self.sha512.as_deref()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UploadSession {
+    id: String,
+    name: String,
+    artifact_type: ArtifactType,
+    size: Option<u64>,
+    sha256: String,
+    expires_at: u64,
+    chunks: Vec<Id>
+}
+
+impl UploadSession {
+    pub fn new<S: Into<String>>(id: S, name: S, artifact_type: ArtifactType, size: Option<u64>, sha256: S, expires_at: u64, chunks: Vec<Id>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            artifact_type,
+            size,
+            sha256: sha256.into(),
+            expires_at,
+            chunks
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn artifact_type(&self) -> ArtifactType {
         self.artifact_type
     }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    pub fn chunks(&self) -> &Vec<Id> {
+        &self.chunks
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -123,14 +326,41 @@ pub enum ArtifactType {
     Other
 }
 
-impl Into<u32> for ArtifactType {
-    fn into(self) -> u32 {
+impl From<ArtifactType> for u32 {
+    fn from(value: ArtifactType) -> Self {
+        match value {
+            ArtifactType::ClientJar => 0,
+            ArtifactType::ServerJar => 1,
+            ArtifactType::Manifest => 2,
+            ArtifactType::MmcInstance => 3,
+            ArtifactType::Other => 4
+        }
+    }
+}
+
+impl ArtifactType {
+    /// Media type files of this type are served as by default.
+    pub fn content_type(self) -> &'static str {
         match self {
-            Self::ClientJar => 0,
-            Self::ServerJar => 1,
-            Self::Manifest => 2,
-            Self::MmcInstance => 3,
-            Self::Other => 4
+            ArtifactType::ClientJar | ArtifactType::ServerJar => "application/java-archive",
+            ArtifactType::Manifest => "application/json",
+            ArtifactType::MmcInstance => "application/zip",
+            ArtifactType::Other => "application/octet-stream"
+        }
+    }
+}
+
+impl FromStr for ArtifactType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ClientJar" => Ok(Self::ClientJar),
+            "ServerJar" => Ok(Self::ServerJar),
+            "Manifest" => Ok(Self::Manifest),
+            "MmcInstance" => Ok(Self::MmcInstance),
+            "Other" => Ok(Self::Other),
+            _ => Err(())
         }
     }
 }
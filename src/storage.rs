@@ -0,0 +1,176 @@
+use std::{io::{self, Read}, path::{Path, PathBuf}, sync::atomic::{AtomicU32, Ordering}, time::{SystemTime, UNIX_EPOCH}};
+
+use axum::extract::multipart::Field;
+use log::warn;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use tokio::{fs::{self, File, OpenOptions}, io::{AsyncReadExt, AsyncWriteExt}};
+
+use crate::{db::is_valid_key, release::Hashes};
+
+/// Directory under the data directory holding the chunks of upload sessions.
+const UPLOADS_DIR: &str = ".uploads";
+
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Computes the size and checksums of a file as it is written.
+#[derive(Default)]
+struct FileHasher {
+    size: u64,
+    sha1: Sha1,
+    sha256: Sha256,
+    sha512: Sha512
+}
+
+impl FileHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+        self.sha1.update(data);
+        self.sha256.update(data);
+        self.sha512.update(data);
+    }
+
+    fn finish(self) -> (u64, Hashes) {
+        let hashes = Hashes::new(
+            Some(hex::encode(self.sha1.finalize())),
+            Some(hex::encode(self.sha256.finalize())),
+            Some(hex::encode(self.sha512.finalize()))
+        );
+        (self.size, hashes)
+    }
+}
+
+/// Generates a new path, relative to the data directory, for a file belonging
+/// to the given release. Returns `None` if any of the keys is not safe to use
+/// as a path component.
+pub fn new_artifact_path(repository_id: &str, channel_id: &str, release_id: &str) -> Option<String> {
+    if !is_valid_key(repository_id) || !is_valid_key(channel_id) || !is_valid_key(release_id) {
+        return None;
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    Some(format!("{repository_id}/{channel_id}/{release_id}/{nanos:x}-{counter:x}"))
+}
+
+/// Resolves the path of a stored file under `data_dir`. Paths are always taken
+/// as relative, even ones starting with `/` from rows that were added by hand.
+pub fn file_path(data_dir: &Path, path: &str) -> PathBuf {
+    data_dir.join("./".to_string() + path)
+}
+
+/// Streams a multipart field into `path` under `data_dir`, returning the number
+/// of bytes written and their checksums. A partially written file is removed
+/// again on failure.
+pub async fn write_field(data_dir: &Path, path: &str, mut field: Field<'_>) -> io::Result<(u64, Hashes)> {
+    let file_path = file_path(data_dir, path);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&file_path).await?;
+    let mut hasher = FileHasher::default();
+    loop {
+        let chunk = match field.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                drop(file);
+                remove_file(data_dir, path).await;
+                return Err(io::Error::other(e));
+            }
+        };
+        if let Err(e) = file.write_all(&chunk).await {
+            drop(file);
+            remove_file(data_dir, path).await;
+            return Err(e);
+        }
+        hasher.update(&chunk);
+    }
+    file.flush().await?;
+
+    Ok(hasher.finish())
+}
+
+/// Removes a file under `data_dir`, logging instead of failing if it can't be removed.
+pub async fn remove_file(data_dir: &Path, path: &str) {
+    if let Err(e) = fs::remove_file(file_path(data_dir, path)).await {
+        warn!("Failed to remove file {}: {}", path, e.to_string());
+    }
+}
+
+/// Path, relative to the data directory, of a chunk of an upload session.
+pub fn upload_chunk_path(session_id: &str, chunk_id: u32) -> String {
+    format!("{UPLOADS_DIR}/{session_id}/{chunk_id}")
+}
+
+/// Writes a chunk of an upload session, replacing any earlier copy of it.
+pub async fn write_upload_chunk(data_dir: &Path, session_id: &str, chunk_id: u32, data: &[u8]) -> io::Result<()> {
+    let file_path = data_dir.join(upload_chunk_path(session_id, chunk_id));
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&file_path, data).await
+}
+
+/// Concatenates the given chunks of an upload session into `path` under
+/// `data_dir`. Returns the number of bytes written and their checksums.
+pub async fn assemble_upload(data_dir: &Path, session_id: &str, chunk_ids: &[u32], path: &str) -> io::Result<(u64, Hashes)> {
+    let file_path = file_path(data_dir, path);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&file_path).await?;
+    let mut hasher = FileHasher::default();
+    let mut buffer = vec![0; 64 * 1024];
+    for chunk_id in chunk_ids {
+        let result: io::Result<()> = async {
+            let mut chunk = File::open(data_dir.join(upload_chunk_path(session_id, *chunk_id))).await?;
+            loop {
+                let read = chunk.read(&mut buffer).await?;
+                if read == 0 {
+                    break Ok(());
+                }
+                hasher.update(&buffer[..read]);
+                file.write_all(&buffer[..read]).await?;
+            }
+        }.await;
+        if let Err(e) = result {
+            drop(file);
+            remove_file(data_dir, path).await;
+            return Err(e);
+        }
+    }
+    file.flush().await?;
+
+    Ok(hasher.finish())
+}
+
+/// Reads a file under `data_dir` to compute its size and checksums.
+pub fn hash_file(data_dir: &Path, path: &str) -> io::Result<(u64, Hashes)> {
+    let mut file = std::fs::File::open(file_path(data_dir, path))?;
+    let mut hasher = FileHasher::default();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break Ok(hasher.finish());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Removes the chunks of an upload session.
+pub async fn remove_upload(data_dir: &Path, session_id: &str) {
+    let dir_path = data_dir.join(UPLOADS_DIR).join(session_id);
+    if let Err(e) = fs::remove_dir_all(&dir_path).await {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove upload session {}: {}", session_id, e.to_string());
+        }
+    }
+}